@@ -0,0 +1,241 @@
+use crate::keying::KeyMatcher;
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Samples used for the fit are taken on a grid sized to about this many pixels.
+const TARGET_SAMPLES: f64 = 40_000.0;
+/// Reweighting rounds that drop subject pixels touching the border.
+const ROBUST_ROUNDS: usize = 3;
+/// Residuals (RGB distance) below this are never treated as outliers.
+const MIN_INLIER_RESIDUAL: f64 = 8.0;
+
+/// Smooth background surface used as a per-pixel key colour.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackgroundModelOptions {
+    /// Polynomial degree in x and y: 1 fits a linear gradient, 2 a vignette.
+    pub degree: u32,
+    /// Width in pixels of the border band sampled as background.
+    pub border_width: u32,
+}
+
+impl Default for BackgroundModelOptions {
+    fn default() -> Self {
+        Self {
+            degree: 2,
+            border_width: 8,
+        }
+    }
+}
+
+/// Per-channel polynomial in normalised coordinates `u, v` in `[-1, 1]`.
+pub struct BackgroundModel {
+    degree: u32,
+    width: u32,
+    height: u32,
+    coefficients: [Vec<f64>; 3],
+}
+
+/// Monomials `u^i v^j` with `i + j <= degree`.
+fn terms(u: f64, v: f64, degree: u32, out: &mut Vec<f64>) {
+    out.clear();
+    for total in 0..=degree as i32 {
+        for i in (0..=total).rev() {
+            out.push(u.powi(i) * v.powi(total - i));
+        }
+    }
+}
+
+/// Solves the square system `a x = b` by Gaussian elimination with partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            let (upper, lower) = a.split_at_mut(row);
+            for (target, value) in lower[0][col..].iter_mut().zip(&upper[col][col..]) {
+                *target -= factor * value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+impl BackgroundModel {
+    /// Fits the surface to the border band and to interior pixels matching `key`,
+    /// dropping outliers such as subject pixels that reach the border.
+    pub fn fit(
+        image: &RgbaImage,
+        key: &KeyMatcher,
+        options: &BackgroundModelOptions,
+    ) -> Option<Self> {
+        let (w, h) = image.dimensions();
+        if w == 0 || h == 0 {
+            return None;
+        }
+        let degree = options.degree.min(4);
+        let band = options.border_width.max(1);
+        let stride = ((w as f64 * h as f64 / TARGET_SAMPLES).sqrt().floor() as u32).max(1);
+
+        let mut samples: Vec<(Vec<f64>, [f64; 3])> = Vec::new();
+        let mut row = Vec::new();
+        for y in (0..h).step_by(stride as usize) {
+            for x in (0..w).step_by(stride as usize) {
+                let p = image.get_pixel(x, y);
+                if p[3] < 128 {
+                    continue;
+                }
+                let border = x < band || y < band || x + band >= w || y + band >= h;
+                if !border && !key.is_key(p) {
+                    continue;
+                }
+                let (u, v) = normalise(x, y, w, h);
+                terms(u, v, degree, &mut row);
+                samples.push((row.clone(), [p[0] as f64, p[1] as f64, p[2] as f64]));
+            }
+        }
+
+        let n = samples.first()?.0.len();
+        let mut weights = vec![1.0f64; samples.len()];
+        let mut coefficients: [Vec<f64>; 3] = Default::default();
+        for _ in 0..ROBUST_ROUNDS {
+            if weights.iter().filter(|&&w| w > 0.0).count() < n * 4 {
+                return None;
+            }
+            let mut normal = vec![vec![0.0; n]; n];
+            let mut rhs = [vec![0.0; n], vec![0.0; n], vec![0.0; n]];
+            for ((terms, rgb), &weight) in samples.iter().zip(&weights) {
+                if weight == 0.0 {
+                    continue;
+                }
+                for i in 0..n {
+                    for j in 0..n {
+                        normal[i][j] += weight * terms[i] * terms[j];
+                    }
+                    for ch in 0..3 {
+                        rhs[ch][i] += weight * terms[i] * rgb[ch];
+                    }
+                }
+            }
+            for ch in 0..3 {
+                coefficients[ch] = solve(normal.clone(), rhs[ch].clone())?;
+            }
+
+            let residuals: Vec<f64> = samples
+                .iter()
+                .map(|(terms, rgb)| {
+                    (0..3)
+                        .map(|ch| {
+                            let fit: f64 = terms
+                                .iter()
+                                .zip(&coefficients[ch])
+                                .map(|(t, c)| t * c)
+                                .sum();
+                            (fit - rgb[ch]).powi(2)
+                        })
+                        .sum::<f64>()
+                        .sqrt()
+                })
+                .collect();
+            let mut sorted: Vec<f64> = residuals
+                .iter()
+                .zip(&weights)
+                .filter(|(_, &w)| w > 0.0)
+                .map(|(r, _)| *r)
+                .collect();
+            sorted.sort_by(f64::total_cmp);
+            let sigma = 1.4826 * sorted[sorted.len() / 2];
+            let limit = (3.0 * sigma).max(MIN_INLIER_RESIDUAL);
+            for (weight, residual) in weights.iter_mut().zip(&residuals) {
+                *weight = if *residual <= limit { 1.0 } else { 0.0 };
+            }
+        }
+
+        Some(Self {
+            degree,
+            width: w,
+            height: h,
+            coefficients,
+        })
+    }
+
+    fn eval(&self, x: u32, y: u32, row: &mut Vec<f64>) -> [u8; 3] {
+        let (u, v) = normalise(x, y, self.width, self.height);
+        terms(u, v, self.degree, row);
+        [0, 1, 2].map(|ch| {
+            let value: f64 = row
+                .iter()
+                .zip(&self.coefficients[ch])
+                .map(|(t, c)| t * c)
+                .sum();
+            value.round().clamp(0.0, 255.0) as u8
+        })
+    }
+
+    /// Background estimate for every pixel, row-major.
+    pub fn render(&self) -> Vec<[u8; 3]> {
+        (0..self.height)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let mut row = Vec::new();
+                (0..self.width).map(move |x| self.eval(x, y, &mut row))
+            })
+            .collect()
+    }
+}
+
+fn normalise(x: u32, y: u32, width: u32, height: u32) -> (f64, f64) {
+    let scale = |pos: u32, size: u32| (2.0 * (pos as f64 + 0.5) / size as f64) - 1.0;
+    (scale(x, width), scale(y, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_fit_follows_vignette_and_ignores_subject() {
+        // Grey backdrop darkening towards the corners, with a dark subject that
+        // reaches the bottom edge.
+        let (w, h) = (160u32, 120u32);
+        let backdrop = |x: u32, y: u32| {
+            let (u, v) = normalise(x, y, w, h);
+            (230.0 - 50.0 * (u * u + v * v)) as u8
+        };
+        let image = RgbaImage::from_fn(w, h, |x, y| {
+            if (60..100).contains(&x) && y >= 50 {
+                Rgba([30, 40, 90, 255])
+            } else {
+                let g = backdrop(x, y);
+                Rgba([g, g, g, 255])
+            }
+        });
+        let key = KeyMatcher::parse("white", 10).unwrap();
+        let model = BackgroundModel::fit(&image, &key, &BackgroundModelOptions::default()).unwrap();
+        let estimate = model.render();
+        let at = |x: u32, y: u32| estimate[(y * w + x) as usize];
+
+        for (x, y) in [(0, 0), (80, 10), (159, 60), (80, 119)] {
+            let expected = backdrop(x, y) as i32;
+            assert!((at(x, y)[0] as i32 - expected).abs() <= 3);
+        }
+        // The corner is far outside the constant key's reach but matches the model.
+        let corner = image.get_pixel(0, 0);
+        assert!(!key.is_key(corner));
+        assert!(key.is_key_near(corner, at(0, 0)));
+        assert!(!key.is_key_near(image.get_pixel(80, 100), at(80, 100)));
+    }
+}
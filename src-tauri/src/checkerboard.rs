@@ -0,0 +1,409 @@
+use crate::flood_fill::{connected_key_mask, FloodFillOptions};
+use crate::image_processing::{image_format_from_path, save_rgba_image_auto, ImageFileFormat};
+use crate::keying::KeyMatcher;
+use image::{Rgba, RgbaImage};
+use std::f32::consts::TAU;
+use std::path::Path;
+
+/// Max channel spread for a pixel to count as a neutral grey.
+const NEUTRAL_SPREAD: u8 = 16;
+/// Max channel difference from a tone for a pixel to match it.
+const TONE_TOLERANCE: f32 = 14.0;
+/// Minimum luma gap between the two checker tones.
+const MIN_TONE_GAP: usize = 10;
+/// Share of the outer band each tone must cover.
+const MIN_TONE_SHARE: f32 = 0.15;
+/// Share of a cell's pixels that must match the tone expected there.
+const CELL_MATCH: f32 = 0.5;
+/// Checker cells need this many checker neighbours, so light subject areas next to
+/// the pattern are not mistaken for cells.
+const MIN_CHECKER_NEIGHBOURS: usize = 3;
+/// Share of the tile the pattern must cover to be reported.
+const MIN_COVERAGE: f32 = 0.05;
+/// Cell boundaries seen before the period is trusted.
+const MIN_RUNS: usize = 8;
+
+#[derive(serde::Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckerboardReport {
+    pub detected: bool,
+    /// Cell size in pixels; fractional once the tile has been resized.
+    pub cell_size: f32,
+    pub light: [u8; 3],
+    pub dark: [u8; 3],
+    /// Share of the tile covered by the checkerboard.
+    pub coverage: f32,
+    /// Whether the tile file was rewritten without the checkerboard.
+    pub removed: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Tone {
+    Light,
+    Dark,
+}
+
+struct CheckerPattern {
+    light: [f32; 3],
+    dark: [f32; 3],
+    period: f32,
+    /// A cell boundary position along x and along y.
+    origin: [f32; 2],
+    /// Cell parity `(cx + cy) % 2` holding the light tone.
+    light_parity: i64,
+}
+
+fn luma(p: &Rgba<u8>) -> f32 {
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+fn is_neutral(p: &Rgba<u8>) -> bool {
+    let max = p[0].max(p[1]).max(p[2]);
+    let min = p[0].min(p[1]).min(p[2]);
+    p[3] >= 128 && max - min <= NEUTRAL_SPREAD
+}
+
+fn near(p: &Rgba<u8>, tone: &[f32; 3]) -> bool {
+    p[3] >= 128 && (0..3).all(|ch| (p[ch] as f32 - tone[ch]).abs() <= TONE_TOLERANCE)
+}
+
+fn tone_of(p: &Rgba<u8>, light: &[f32; 3], dark: &[f32; 3]) -> Option<Tone> {
+    if near(p, light) {
+        Some(Tone::Light)
+    } else if near(p, dark) {
+        Some(Tone::Dark)
+    } else {
+        None
+    }
+}
+
+/// The two dominant neutral tones in the outer band, light first.
+fn estimate_tones(image: &RgbaImage, band: u32) -> Option<([f32; 3], [f32; 3])> {
+    let (w, h) = image.dimensions();
+    let mut hist = [0u32; 256];
+    let mut sums = [[0f64; 3]; 256];
+    let mut total = 0u32;
+    for (x, y, p) in image.enumerate_pixels() {
+        if x >= band && y >= band && x + band < w && y + band < h {
+            continue;
+        }
+        total += 1;
+        if !is_neutral(p) {
+            continue;
+        }
+        let bin = luma(p).round() as usize;
+        hist[bin] += 1;
+        for ch in 0..3 {
+            sums[bin][ch] += p[ch] as f64;
+        }
+    }
+
+    let bins = |c: usize| c.saturating_sub(3)..=(c + 3).min(255);
+    let window = |c: usize| bins(c).map(|i| hist[i]).sum::<u32>();
+    let first = (0..256usize).max_by_key(|&c| window(c))?;
+    let second = (0..256usize)
+        .filter(|&c| c.abs_diff(first) >= MIN_TONE_GAP)
+        .max_by_key(|&c| window(c))?;
+    let mut tones = [[0f32; 3]; 2];
+    for (tone, peak) in tones.iter_mut().zip([first, second]) {
+        let count = window(peak);
+        if (count as f32) < total as f32 * MIN_TONE_SHARE {
+            return None;
+        }
+        for ch in 0..3 {
+            let sum: f64 = bins(peak).map(|i| sums[i][ch]).sum();
+            tone[ch] = (sum / count as f64) as f32;
+        }
+    }
+    if first > second {
+        Some((tones[0], tones[1]))
+    } else {
+        Some((tones[1], tones[0]))
+    }
+}
+
+/// Tone boundaries along a line of labels, tolerating one unlabelled pixel of
+/// anti-aliasing between cells. Returns boundary positions and the run lengths
+/// between consecutive boundaries.
+fn line_boundaries(labels: &[Option<Tone>]) -> (Vec<f32>, Vec<f32>) {
+    let mut boundaries = Vec::new();
+    let mut runs = Vec::new();
+    let mut last: Option<(usize, Tone)> = None;
+    let mut run_start: Option<f32> = None;
+    for (i, label) in labels.iter().enumerate() {
+        let Some(tone) = *label else {
+            continue;
+        };
+        if let Some((j, previous)) = last {
+            if i - j > 2 {
+                run_start = None;
+            } else if previous != tone {
+                let boundary = (j + 1 + i) as f32 / 2.0;
+                if let Some(start) = run_start {
+                    runs.push(boundary - start);
+                }
+                boundaries.push(boundary);
+                run_start = Some(boundary);
+            }
+        }
+        last = Some((i, tone));
+    }
+    (boundaries, runs)
+}
+
+/// Circular mean of boundary positions modulo `period`.
+fn phase(boundaries: &[f32], period: f32) -> Option<f32> {
+    if boundaries.is_empty() {
+        return None;
+    }
+    let (sin, cos) = boundaries.iter().fold((0.0f32, 0.0f32), |(s, c), &b| {
+        let angle = TAU * b / period;
+        (s + angle.sin(), c + angle.cos())
+    });
+    Some((sin.atan2(cos) / TAU * period).rem_euclid(period))
+}
+
+impl CheckerPattern {
+    fn estimate(image: &RgbaImage) -> Option<Self> {
+        let (w, h) = image.dimensions();
+        let band = (w.min(h) / 8).max(4).min(w.min(h));
+        let (light, dark) = estimate_tones(image, band)?;
+        let label = |x: u32, y: u32| tone_of(image.get_pixel(x, y), &light, &dark);
+
+        let mut x_bounds = Vec::new();
+        let mut y_bounds = Vec::new();
+        let mut runs = Vec::new();
+        for y in (0..band).chain(h.saturating_sub(band).max(band)..h) {
+            let (b, r) = line_boundaries(&(0..w).map(|x| label(x, y)).collect::<Vec<_>>());
+            x_bounds.extend(b);
+            runs.extend(r);
+        }
+        for x in (0..band).chain(w.saturating_sub(band).max(band)..w) {
+            let (b, r) = line_boundaries(&(0..h).map(|y| label(x, y)).collect::<Vec<_>>());
+            y_bounds.extend(b);
+            runs.extend(r);
+        }
+        runs.retain(|&r| r >= 2.0);
+        if runs.len() < MIN_RUNS {
+            return None;
+        }
+
+        // Most common run length, refined by averaging the runs around it.
+        let mut counts = vec![0u32; w.max(h) as usize + 1];
+        let top = counts.len() - 1;
+        for &r in &runs {
+            counts[(r.round() as usize).min(top)] += 1;
+        }
+        let mode = (0..counts.len()).max_by_key(|&i| counts[i])? as f32;
+        let near_mode: Vec<f32> = runs
+            .into_iter()
+            .filter(|r| (r - mode).abs() <= 1.5)
+            .collect();
+        let period = near_mode.iter().sum::<f32>() / near_mode.len() as f32;
+        if period * 2.0 > w.min(h) as f32 {
+            return None;
+        }
+
+        let mut pattern = Self {
+            light,
+            dark,
+            period,
+            origin: [phase(&x_bounds, period)?, phase(&y_bounds, period)?],
+            light_parity: 0,
+        };
+        let mut votes = [0i64; 2];
+        for y in (0..h).step_by(2) {
+            for x in (0..w).step_by(2) {
+                let ((cx, cy), edge) = pattern.cell(x, y);
+                if edge {
+                    continue;
+                }
+                let parity = (cx + cy).rem_euclid(2) as usize;
+                match label(x, y) {
+                    Some(Tone::Light) => votes[parity] += 1,
+                    Some(Tone::Dark) => votes[1 - parity] += 1,
+                    None => {}
+                }
+            }
+        }
+        pattern.light_parity = if votes[0] >= votes[1] { 0 } else { 1 };
+        Some(pattern)
+    }
+
+    /// Cell of a pixel, and whether the pixel straddles a cell boundary.
+    fn cell(&self, x: u32, y: u32) -> ((i64, i64), bool) {
+        let mut index = [0i64; 2];
+        let mut edge = false;
+        for (axis, pos) in [x, y].into_iter().enumerate() {
+            let t = (pos as f32 + 0.5 - self.origin[axis]) / self.period;
+            let frac = t - t.floor();
+            index[axis] = t.floor() as i64;
+            edge |= frac.min(1.0 - frac) * self.period < 0.5;
+        }
+        ((index[0], index[1]), edge)
+    }
+
+    /// Whether a pixel looks like the checkerboard at its position. Pixels on cell
+    /// boundaries may be any blend of the two tones.
+    fn matches(&self, (cx, cy): (i64, i64), edge: bool, p: &Rgba<u8>) -> bool {
+        if edge {
+            let l = luma(p);
+            let (lo, hi) = (luma_of(&self.dark), luma_of(&self.light));
+            return is_neutral(p) && l >= lo - TONE_TOLERANCE && l <= hi + TONE_TOLERANCE;
+        }
+        let expected = if (cx + cy).rem_euclid(2) == self.light_parity {
+            &self.light
+        } else {
+            &self.dark
+        };
+        near(p, expected)
+    }
+}
+
+fn luma_of(tone: &[f32; 3]) -> f32 {
+    0.299 * tone[0] + 0.587 * tone[1] + 0.114 * tone[2]
+}
+
+/// Detects a painted transparency checkerboard and returns the report with the
+/// pixels to clear: checker-matching pixels in accepted cells reachable from the border.
+pub fn detect_checkerboard(image: &RgbaImage) -> (CheckerboardReport, Vec<bool>) {
+    let (w, h) = image.dimensions();
+    let mut mask = vec![false; (w as usize) * (h as usize)];
+    let Some(pattern) = CheckerPattern::estimate(image) else {
+        return (CheckerboardReport::default(), mask);
+    };
+
+    let first = pattern.cell(0, 0).0;
+    let last = pattern.cell(w - 1, h - 1).0;
+    let cells_x = (last.0 - first.0 + 1) as usize;
+    let cells_y = (last.1 - first.1 + 1) as usize;
+    let cell_index =
+        |(cx, cy): (i64, i64)| (cy - first.1) as usize * cells_x + (cx - first.0) as usize;
+    let mut matched = vec![0u32; cells_x * cells_y];
+    let mut totals = vec![0u32; cells_x * cells_y];
+    let matching: Vec<bool> = image
+        .enumerate_pixels()
+        .map(|(x, y, p)| {
+            let (cell, edge) = pattern.cell(x, y);
+            let hit = pattern.matches(cell, edge, p);
+            // Boundary pixels match almost any light neutral, so only interiors vote.
+            if !edge {
+                totals[cell_index(cell)] += 1;
+                matched[cell_index(cell)] += hit as u32;
+            }
+            hit
+        })
+        .collect();
+
+    let candidate: Vec<bool> = (0..matched.len())
+        .map(|idx| totals[idx] > 0 && matched[idx] as f32 >= totals[idx] as f32 * CELL_MATCH)
+        .collect();
+    // Neighbours outside the image count, so border and corner cells can qualify.
+    let accepted: Vec<bool> = (0..candidate.len())
+        .map(|idx| {
+            let (cx, cy) = ((idx % cells_x) as i64, (idx / cells_x) as i64);
+            let neighbours = [(cx - 1, cy), (cx + 1, cy), (cx, cy - 1), (cx, cy + 1)]
+                .into_iter()
+                .filter(|&(nx, ny)| {
+                    nx < 0
+                        || ny < 0
+                        || nx >= cells_x as i64
+                        || ny >= cells_y as i64
+                        || candidate[ny as usize * cells_x + nx as usize]
+                })
+                .count();
+            candidate[idx] && neighbours >= MIN_CHECKER_NEIGHBOURS
+        })
+        .collect();
+
+    let keyable: Vec<bool> = image
+        .enumerate_pixels()
+        .zip(&matching)
+        .map(|((x, y, _), &hit)| hit && accepted[cell_index(pattern.cell(x, y).0)])
+        .collect();
+    mask = connected_key_mask(&keyable, w, h, &FloodFillOptions::default());
+
+    let coverage = mask.iter().filter(|&&m| m).count() as f32 / mask.len() as f32;
+    let to_u8 = |tone: [f32; 3]| tone.map(|v| v.round().clamp(0.0, 255.0) as u8);
+    let report = CheckerboardReport {
+        detected: coverage >= MIN_COVERAGE,
+        cell_size: pattern.period,
+        light: to_u8(pattern.light),
+        dark: to_u8(pattern.dark),
+        coverage,
+        removed: false,
+    };
+    if !report.detected {
+        mask.fill(false);
+    }
+    (report, mask)
+}
+
+/// Checks a generated tile for a painted checkerboard. With `remove`, the pattern is
+/// made transparent in PNG tiles, or painted with `key_color` in JPEG tiles so the
+/// merge keys it out; without a key colour JPEG tiles are only reported.
+pub fn check_tile_checkerboard(
+    path: &str,
+    remove: bool,
+    key_color: Option<&str>,
+) -> Result<CheckerboardReport, String> {
+    let mut image = image::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?
+        .to_rgba8();
+    let (mut report, mask) = detect_checkerboard(&image);
+    if !remove || !report.detected {
+        return Ok(report);
+    }
+
+    let fill = match (image_format_from_path(Path::new(path)), key_color) {
+        (ImageFileFormat::Png, _) => Rgba([0, 0, 0, 0]),
+        (ImageFileFormat::Jpeg, Some(key_color)) => {
+            let [r, g, b] = KeyMatcher::parse(key_color, 0)?.fill_rgb();
+            Rgba([r, g, b, 255])
+        }
+        (ImageFileFormat::Jpeg, None) => return Ok(report),
+    };
+    for (px, &clear) in image.pixels_mut().zip(&mask) {
+        if clear {
+            *px = fill;
+        }
+    }
+    save_rgba_image_auto(path, &image)?;
+    report.removed = true;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_fractional_checkerboard_and_keeps_subject() {
+        // 10.5 px cells, as after resizing a generated tile, around a white square
+        // subject and a red disc.
+        let image = RgbaImage::from_fn(120, 120, |x, y| {
+            let (cx, cy) = ((x as f32 / 10.5) as u32, (y as f32 / 10.5) as u32);
+            let (dx, dy) = (x as f32 - 85.0, y as f32 - 85.0);
+            if (33..63).contains(&x) && (33..63).contains(&y) {
+                Rgba([255, 255, 255, 255])
+            } else if dx * dx + dy * dy < 400.0 {
+                Rgba([220, 30, 30, 255])
+            } else if (cx + cy) % 2 == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([204, 204, 204, 255])
+            }
+        });
+
+        let (report, mask) = detect_checkerboard(&image);
+        assert!(report.detected);
+        assert!((report.cell_size - 10.5).abs() < 0.3);
+        assert_eq!(report.dark, [204, 204, 204]);
+        assert!(mask[5 * 120 + 5] && mask[110 * 120 + 20]);
+        assert!(!mask[36 * 120 + 36] && !mask[48 * 120 + 48]);
+        assert!(!mask[85 * 120 + 85]);
+
+        let flat = RgbaImage::from_pixel(64, 64, Rgba([240, 240, 240, 255]));
+        assert!(!detect_checkerboard(&flat).0.detected);
+    }
+}
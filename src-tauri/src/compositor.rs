@@ -0,0 +1,236 @@
+use crate::filters::ColorSpace;
+use image::{Rgba, RgbaImage};
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    SoftLight,
+    Darken,
+    Lighten,
+    Color,
+}
+
+/// A decoded overlay placed at `(x, y)` in merged-image coordinates.
+pub struct PlacedOverlay<'a> {
+    pub x: u32,
+    pub y: u32,
+    pub image: &'a RgbaImage,
+    /// Layer opacity (0.0 - 1.0), applied on top of the overlay's own alpha.
+    pub opacity: f32,
+    pub blend: BlendMode,
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    let c = [c[0] + d, c[1] + d, c[2] + d];
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut out = c;
+    for v in out.iter_mut() {
+        if n < 0.0 {
+            *v = l + (*v - l) * l / (l - n).max(1e-6);
+        }
+        if x > 1.0 {
+            *v = l + (*v - l) * (1.0 - l) / (x - l).max(1e-6);
+        }
+    }
+    out
+}
+
+fn soft_light(cb: f32, cs: f32) -> f32 {
+    if cs <= 0.5 {
+        cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+    } else {
+        let d = if cb <= 0.25 {
+            ((16.0 * cb - 12.0) * cb + 4.0) * cb
+        } else {
+            cb.sqrt()
+        };
+        cb + (2.0 * cs - 1.0) * (d - cb)
+    }
+}
+
+/// Blend function `B(Cb, Cs)` from the W3C compositing spec, on straight colour in 0..1.
+fn blend_colour(mode: BlendMode, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+    let separable = |f: fn(f32, f32) -> f32| [f(cb[0], cs[0]), f(cb[1], cs[1]), f(cb[2], cs[2])];
+    match mode {
+        BlendMode::Normal => cs,
+        BlendMode::Multiply => separable(|b, s| b * s),
+        BlendMode::Screen => separable(|b, s| b + s - b * s),
+        BlendMode::Overlay => separable(|b, s| {
+            if b <= 0.5 {
+                2.0 * b * s
+            } else {
+                1.0 - 2.0 * (1.0 - b) * (1.0 - s)
+            }
+        }),
+        BlendMode::SoftLight => separable(soft_light),
+        BlendMode::Darken => separable(f32::min),
+        BlendMode::Lighten => separable(f32::max),
+        BlendMode::Color => set_lum(cs, lum(cb)),
+    }
+}
+
+/// Composites `overlay` onto `base` at `(x, y)` with straight alpha. In sRGB this
+/// matches what the canvas preview does with `globalAlpha` and `globalCompositeOperation`.
+pub fn composite_layer(
+    base: &mut RgbaImage,
+    overlay: &RgbaImage,
+    x: u32,
+    y: u32,
+    opacity: f32,
+    blend: BlendMode,
+    space: ColorSpace,
+) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    if opacity <= 0.0 {
+        return;
+    }
+    let (bw, bh) = base.dimensions();
+    let x2 = x.saturating_add(overlay.width()).min(bw);
+    let y2 = y.saturating_add(overlay.height()).min(bh);
+
+    for gy in y..y2 {
+        for gx in x..x2 {
+            let src = overlay.get_pixel(gx - x, gy - y);
+            let sa = src[3] as f32 / 255.0 * opacity;
+            if sa <= 0.0 {
+                continue;
+            }
+            let dst = base.get_pixel_mut(gx, gy);
+            if sa >= 1.0 && blend == BlendMode::Normal {
+                *dst = *src;
+                continue;
+            }
+
+            let da = dst[3] as f32 / 255.0;
+            let cs = [0, 1, 2].map(|ch| space.decode(src[ch]));
+            let cb = [0, 1, 2].map(|ch| space.decode(dst[ch]));
+            let mixed = blend_colour(blend, cb, cs);
+            let out_a = sa + da * (1.0 - sa);
+            let mut out = Rgba([0, 0, 0, (out_a * 255.0).round().clamp(0.0, 255.0) as u8]);
+            for ch in 0..3 {
+                // Where the backdrop is transparent the source colour shows unblended.
+                let source = (1.0 - da) * cs[ch] + da * mixed[ch];
+                let value = (source * sa + cb[ch] * da * (1.0 - sa)) / out_a.max(1e-6);
+                out[ch] = space.encode(value);
+            }
+            *dst = out;
+        }
+    }
+}
+
+/// Flattens overlays onto a copy of `base`, bottom-most first.
+pub fn flatten_overlays(
+    base: &RgbaImage,
+    overlays: &[PlacedOverlay],
+    space: ColorSpace,
+) -> RgbaImage {
+    let mut flattened = base.clone();
+    for overlay in overlays {
+        composite_layer(
+            &mut flattened,
+            overlay.image,
+            overlay.x,
+            overlay.y,
+            overlay.opacity,
+            overlay.blend,
+            space,
+        );
+    }
+    flattened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flatten_overlays_in_order_with_alpha() {
+        let base = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        let red = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let half_green = RgbaImage::from_pixel(2, 2, Rgba([0, 255, 0, 128]));
+
+        let out = flatten_overlays(
+            &base,
+            &[
+                PlacedOverlay {
+                    x: 1,
+                    y: 1,
+                    image: &red,
+                    opacity: 1.0,
+                    blend: BlendMode::Normal,
+                },
+                PlacedOverlay {
+                    x: 2,
+                    y: 2,
+                    image: &half_green,
+                    opacity: 1.0,
+                    blend: BlendMode::Normal,
+                },
+            ],
+            ColorSpace::Srgb,
+        );
+
+        assert_eq!(*out.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*out.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+        let mixed = out.get_pixel(2, 2);
+        assert!(mixed[0].abs_diff(127) <= 1 && mixed[1].abs_diff(128) <= 1);
+        assert_eq!(mixed[3], 255);
+        // Overlay clipped at the image edge.
+        assert_eq!(out.get_pixel(3, 3)[2], 127);
+    }
+
+    #[test]
+    fn test_blend_modes_and_opacity() {
+        let grey = Rgba([128, 128, 128, 255]);
+        let white = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        let black = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255]));
+
+        let mut base = RgbaImage::from_pixel(1, 1, grey);
+        composite_layer(
+            &mut base,
+            &white,
+            0,
+            0,
+            1.0,
+            BlendMode::Multiply,
+            ColorSpace::Srgb,
+        );
+        assert_eq!(*base.get_pixel(0, 0), grey);
+
+        let mut base = RgbaImage::from_pixel(1, 1, grey);
+        composite_layer(
+            &mut base,
+            &black,
+            0,
+            0,
+            1.0,
+            BlendMode::Screen,
+            ColorSpace::Srgb,
+        );
+        assert_eq!(*base.get_pixel(0, 0), grey);
+
+        let mut base = RgbaImage::from_pixel(1, 1, grey);
+        composite_layer(
+            &mut base,
+            &black,
+            0,
+            0,
+            0.25,
+            BlendMode::Normal,
+            ColorSpace::Srgb,
+        );
+        assert_eq!(base.get_pixel(0, 0)[0], 96);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::filters::resize_rgba;
+use crate::image_processing::save_rgba_image_auto;
+use image::imageops::FilterType as ResizeFilterType;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Paths of the on-white and on-black generations of a tile, stored next to its
+/// output as `<stem>_on_white.png` and `<stem>_on_black.png`.
+pub fn dual_background_paths(tile_path: &str) -> (PathBuf, PathBuf) {
+    let path = Path::new(tile_path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    (
+        dir.join(format!("{}_on_white.png", stem)),
+        dir.join(format!("{}_on_black.png", stem)),
+    )
+}
+
+pub fn has_dual_background_pair(tile_path: &str) -> bool {
+    let (white, black) = dual_background_paths(tile_path);
+    white.is_file() && black.is_file()
+}
+
+/// Solves alpha and foreground colour from the same subject composited over white
+/// and over black: `on_white - on_black = (1 - alpha) * 255` for every channel.
+pub fn solve_alpha(on_white: &RgbaImage, on_black: &RgbaImage) -> Result<RgbaImage, String> {
+    if on_white.dimensions() != on_black.dimensions() {
+        return Err("White and black generations differ in size".to_string());
+    }
+    let (w, h) = on_white.dimensions();
+    let mut solved = RgbaImage::new(w, h);
+    solved
+        .par_chunks_mut(4)
+        .zip(on_white.par_chunks(4).zip(on_black.par_chunks(4)))
+        .for_each(|(out, (white, black))| {
+            let spread = (0..3)
+                .map(|ch| white[ch] as f32 - black[ch] as f32)
+                .sum::<f32>()
+                / 3.0;
+            let alpha = (1.0 - spread / 255.0).clamp(0.0, 1.0);
+            if alpha <= 0.0 {
+                out.copy_from_slice(&[0, 0, 0, 0]);
+                return;
+            }
+            let mut pixel = Rgba([0, 0, 0, (alpha * 255.0).round() as u8]);
+            for ch in 0..3 {
+                // Average the foreground recovered from each background.
+                let from_black = black[ch] as f32;
+                let from_white = white[ch] as f32 - (1.0 - alpha) * 255.0;
+                let colour = (from_black + from_white) / (2.0 * alpha);
+                pixel[ch] = colour.round().clamp(0.0, 255.0) as u8;
+            }
+            out.copy_from_slice(&pixel.0);
+        });
+    Ok(solved)
+}
+
+/// Loads a tile's generation pair at `width` x `height` and solves it.
+pub fn solve_dual_tile(tile_path: &str, width: u32, height: u32) -> Result<RgbaImage, String> {
+    let (white_path, black_path) = dual_background_paths(tile_path);
+    let load = |path: &Path| -> Result<RgbaImage, String> {
+        let img = image::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?
+            .to_rgba8();
+        Ok(resize_rgba(&img, width, height, ResizeFilterType::Lanczos3))
+    };
+    let (on_white, on_black) = rayon::join(|| load(&white_path), || load(&black_path));
+    solve_alpha(&on_white?, &on_black?)
+}
+
+/// Solves a tile's generation pair and writes the RGBA result to the tile output.
+pub fn solve_dual_tile_to_output(tile_path: &str, width: u32, height: u32) -> Result<(), String> {
+    let solved = solve_dual_tile(tile_path, width, height)?;
+    save_rgba_image_auto(tile_path, &solved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_alpha_recovers_semi_transparent_colour() {
+        // 40% opaque red glass over white and over black.
+        let on_white = RgbaImage::from_pixel(2, 2, Rgba([255, 153, 153, 255]));
+        let on_black = RgbaImage::from_pixel(2, 2, Rgba([102, 0, 0, 255]));
+        let solved = solve_alpha(&on_white, &on_black).unwrap();
+        let p = solved.get_pixel(1, 1);
+        assert_eq!(p[3], 102);
+        assert!(p[0] >= 254 && p[1] <= 1 && p[2] <= 1);
+
+        let white = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        let black = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255]));
+        assert_eq!(solve_alpha(&white, &black).unwrap().get_pixel(0, 0)[3], 0);
+
+        let (w, b) = dual_background_paths("/tmp/session/tile_1_2.jpg");
+        assert_eq!(w, Path::new("/tmp/session/tile_1_2_on_white.png"));
+        assert_eq!(b, Path::new("/tmp/session/tile_1_2_on_black.png"));
+    }
+}
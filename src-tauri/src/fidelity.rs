@@ -0,0 +1,212 @@
+use crate::filters::{box_blur, rgba_to_rgb_f32, smoothstep};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DetailTransferOptions {
+    /// Box blur radius separating the low- and high-frequency bands.
+    pub radius: u32,
+    /// How much of the original's high-frequency band to restore (0.0 - 1.0).
+    pub strength: f32,
+    /// Mean low-frequency difference (0-255) above which tiles are treated as disagreeing.
+    pub agreement_threshold: f32,
+}
+
+impl Default for DetailTransferOptions {
+    fn default() -> Self {
+        Self {
+            radius: 2,
+            strength: 1.0,
+            agreement_threshold: 12.0,
+        }
+    }
+}
+
+/// Replaces the high-frequency band of `processed` with the one from `original`
+/// wherever both tiles agree in their low-frequency content.
+///
+/// Pixels for which `is_background` returns true are left untouched, as are
+/// areas where the generated tile deviates from the original (changed background).
+pub fn transfer_detail<F>(
+    processed: &mut RgbaImage,
+    original: &RgbaImage,
+    options: &DetailTransferOptions,
+    is_background: F,
+) where
+    F: Fn(&Rgba<u8>) -> bool,
+{
+    if processed.dimensions() != original.dimensions() || options.strength <= 0.0 {
+        return;
+    }
+
+    let (w, h) = processed.dimensions();
+    let (w, h) = (w as usize, h as usize);
+    let radius = options.radius.max(1) as usize;
+    let strength = options.strength.min(1.0);
+    let threshold = options.agreement_threshold.max(1.0);
+
+    let proc_rgb = rgba_to_rgb_f32(processed);
+    let orig_rgb = rgba_to_rgb_f32(original);
+    let proc_low = box_blur(&proc_rgb, w, h, 3, radius);
+    let orig_low = box_blur(&orig_rgb, w, h, 3, radius);
+
+    for (idx, px) in processed.pixels_mut().enumerate() {
+        if px[3] < 10 || is_background(px) {
+            continue;
+        }
+
+        let base = idx * 3;
+        let low_diff = (0..3)
+            .map(|ch| (proc_low[base + ch] - orig_low[base + ch]).abs())
+            .sum::<f32>()
+            / 3.0;
+        let agreement = 1.0 - smoothstep(threshold * 0.5, threshold, low_diff);
+        if agreement <= 0.0 {
+            continue;
+        }
+
+        let weight = agreement * strength;
+        for ch in 0..3 {
+            let orig_high = orig_rgb[base + ch] - orig_low[base + ch];
+            let proc_high = proc_rgb[base + ch] - proc_low[base + ch];
+            let value = proc_rgb[base + ch] + weight * (orig_high - proc_high);
+            px[ch] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SubjectGuardOptions {
+    /// Largest per-channel change (0-255) tolerated inside the subject before reverting.
+    pub threshold: u8,
+    /// Radius used to feather the reverted areas into the processed tile.
+    pub feather_radius: u32,
+}
+
+impl Default for SubjectGuardOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 48,
+            feather_radius: 4,
+        }
+    }
+}
+
+/// Reverts subject pixels that the model changed by more than the guard threshold.
+///
+/// The subject is everything `is_background` rejects in the processed tile. Returns
+/// the feathered revert weight per pixel (255 = fully restored from the original).
+pub fn guard_subject<F>(
+    processed: &mut RgbaImage,
+    original: &RgbaImage,
+    options: &SubjectGuardOptions,
+    is_background: F,
+) -> GrayImage
+where
+    F: Fn(&Rgba<u8>) -> bool,
+{
+    let (w, h) = processed.dimensions();
+    let mut mask = GrayImage::new(w, h);
+    if original.dimensions() != (w, h) {
+        return mask;
+    }
+
+    let subject: Vec<bool> = processed.pixels().map(|p| !is_background(p)).collect();
+    let changed: Vec<f32> = processed
+        .pixels()
+        .zip(original.pixels())
+        .zip(subject.iter())
+        .map(|((p, o), &is_subject)| {
+            let diff = (0..3).map(|ch| p[ch].abs_diff(o[ch])).max().unwrap_or(0);
+            if is_subject && diff > options.threshold {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    let radius = options.feather_radius as usize;
+    let feathered = box_blur(&changed, w as usize, h as usize, 1, radius);
+
+    for (idx, (px, orig)) in processed.pixels_mut().zip(original.pixels()).enumerate() {
+        if !subject[idx] {
+            continue;
+        }
+        // Double the blurred coverage so small reverted spots still restore fully.
+        let weight = (feathered[idx] * 2.0).min(1.0).max(changed[idx]);
+        if weight <= 0.0 {
+            continue;
+        }
+        for ch in 0..4 {
+            let value = px[ch] as f32 + weight * (orig[ch] as f32 - px[ch] as f32);
+            px[ch] = value.round().clamp(0.0, 255.0) as u8;
+        }
+        mask.put_pixel(
+            (idx as u32) % w,
+            (idx as u32) / w,
+            Luma([(weight * 255.0).round() as u8]),
+        );
+    }
+
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([160, 160, 160, 255])
+            } else {
+                Rgba([100, 100, 100, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_transfer_detail_restores_texture_only_where_tiles_agree() {
+        let original = checker(16);
+        // Left half: softened copy of the original. Right half: replaced background.
+        let mut processed = RgbaImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                Rgba([130, 130, 130, 255])
+            } else {
+                Rgba([250, 250, 250, 255])
+            }
+        });
+
+        transfer_detail(
+            &mut processed,
+            &original,
+            &DetailTransferOptions::default(),
+            |p| p[0] > 240,
+        );
+
+        assert_ne!(processed.get_pixel(2, 2)[0], processed.get_pixel(3, 2)[0]);
+        assert_eq!(processed.get_pixel(13, 2)[0], 250);
+    }
+
+    #[test]
+    fn test_guard_subject_reverts_altered_subject_pixels() {
+        let original = RgbaImage::from_pixel(12, 12, Rgba([40, 80, 120, 255]));
+        let mut processed = original.clone();
+        processed.put_pixel(6, 6, Rgba([200, 10, 10, 255]));
+        processed.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+
+        let mask = guard_subject(
+            &mut processed,
+            &original,
+            &SubjectGuardOptions::default(),
+            |p| p[0] > 240 && p[1] > 240 && p[2] > 240,
+        );
+
+        assert_eq!(*processed.get_pixel(6, 6), Rgba([40, 80, 120, 255]));
+        assert_eq!(mask.get_pixel(6, 6)[0], 255);
+        assert_eq!(*processed.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(mask.get_pixel(0, 0)[0], 0);
+    }
+}
@@ -0,0 +1,267 @@
+use image::imageops::FilterType as ResizeFilterType;
+use image::{DynamicImage, Rgba, Rgba32FImage, RgbaImage};
+use rayon::prelude::*;
+use std::sync::OnceLock;
+
+/// Converts the colour channels of an RGBA image into interleaved `f32` RGB samples.
+pub fn rgba_to_rgb_f32(image: &RgbaImage) -> Vec<f32> {
+    let mut out = Vec::with_capacity((image.width() * image.height() * 3) as usize);
+    for px in image.pixels() {
+        out.extend_from_slice(&[px[0] as f32, px[1] as f32, px[2] as f32]);
+    }
+    out
+}
+
+/// Separable box blur over interleaved `f32` samples. Edges are clamped.
+pub fn box_blur(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    channels: usize,
+    radius: usize,
+) -> Vec<f32> {
+    if radius == 0 || width == 0 || height == 0 {
+        return data.to_vec();
+    }
+
+    let row_len = width * channels;
+    let window = (2 * radius + 1) as f32;
+
+    // Horizontal pass.
+    let mut horizontal = vec![0.0f32; data.len()];
+    horizontal
+        .par_chunks_mut(row_len)
+        .zip(data.par_chunks(row_len))
+        .for_each(|(dst, src)| {
+            for ch in 0..channels {
+                let at = |x: isize| -> f32 {
+                    let cx = x.clamp(0, width as isize - 1) as usize;
+                    src[cx * channels + ch]
+                };
+                let mut sum = 0.0f32;
+                for dx in -(radius as isize)..=(radius as isize) {
+                    sum += at(dx);
+                }
+                for x in 0..width {
+                    dst[x * channels + ch] = sum / window;
+                    sum += at(x as isize + radius as isize + 1) - at(x as isize - radius as isize);
+                }
+            }
+        });
+
+    // Vertical pass, processed in column strips so each strip is independent.
+    let mut out = vec![0.0f32; data.len()];
+    let columns: Vec<Vec<f32>> = (0..row_len)
+        .into_par_iter()
+        .map(|col| {
+            let at = |y: isize| -> f32 {
+                let cy = y.clamp(0, height as isize - 1) as usize;
+                horizontal[cy * row_len + col]
+            };
+            let mut column = Vec::with_capacity(height);
+            let mut sum = 0.0f32;
+            for dy in -(radius as isize)..=(radius as isize) {
+                sum += at(dy);
+            }
+            for y in 0..height {
+                column.push(sum / window);
+                sum += at(y as isize + radius as isize + 1) - at(y as isize - radius as isize);
+            }
+            column
+        })
+        .collect();
+    for (col, column) in columns.iter().enumerate() {
+        for (y, value) in column.iter().enumerate() {
+            out[y * row_len + col] = *value;
+        }
+    }
+    out
+}
+
+/// Hermite smoothstep between `edge0` and `edge1`.
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge1 <= edge0 {
+        return if x < edge0 { 0.0 } else { 1.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Space pixels are blended, resized and composited in.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorSpace {
+    /// Gamma-encoded bytes as stored, matching the canvas preview.
+    #[default]
+    Srgb,
+    /// Linear light; avoids darkened blends and reduces ringing on hard edges.
+    Linear,
+}
+
+/// Steps of the linear-to-sRGB encode table.
+const ENCODE_STEPS: usize = 4096;
+
+fn srgb_tables() -> &'static ([f32; 256], Vec<u8>) {
+    static TABLES: OnceLock<([f32; 256], Vec<u8>)> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let decode = std::array::from_fn(|v| {
+            let c = v as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        let encode = (0..=ENCODE_STEPS)
+            .map(|i| {
+                let l = i as f32 / ENCODE_STEPS as f32;
+                let c = if l <= 0.0031308 {
+                    l * 12.92
+                } else {
+                    1.055 * l.powf(1.0 / 2.4) - 0.055
+                };
+                (c * 255.0).round().clamp(0.0, 255.0) as u8
+            })
+            .collect();
+        (decode, encode)
+    })
+}
+
+impl ColorSpace {
+    /// Colour channel byte to a 0..1 value in this space.
+    pub fn decode(self, v: u8) -> f32 {
+        match self {
+            Self::Srgb => v as f32 / 255.0,
+            Self::Linear => srgb_tables().0[v as usize],
+        }
+    }
+
+    /// 0..1 value in this space back to an sRGB byte.
+    pub fn encode(self, v: f32) -> u8 {
+        let v = v.clamp(0.0, 1.0);
+        match self {
+            Self::Srgb => (v * 255.0).round() as u8,
+            Self::Linear => srgb_tables().1[(v * ENCODE_STEPS as f32).round() as usize],
+        }
+    }
+}
+
+/// Linear mix of two straight-alpha pixels, `t` weighting `b`, done on premultiplied
+/// colour so transparent pixels do not pull the result towards black.
+pub fn lerp_premultiplied(a: &Rgba<u8>, b: &Rgba<u8>, t: f32) -> Rgba<u8> {
+    lerp_premultiplied_in(a, b, t, ColorSpace::Srgb)
+}
+
+/// [`lerp_premultiplied`] with the colour channels mixed in `space`.
+pub fn lerp_premultiplied_in(a: &Rgba<u8>, b: &Rgba<u8>, t: f32, space: ColorSpace) -> Rgba<u8> {
+    let t = t.clamp(0.0, 1.0);
+    let (aa, ba) = (a[3] as f32 / 255.0, b[3] as f32 / 255.0);
+    let alpha = aa + t * (ba - aa);
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let mut out = Rgba([0, 0, 0, (alpha * 255.0).round().clamp(0.0, 255.0) as u8]);
+    for ch in 0..3 {
+        let (ca, cb) = (space.decode(a[ch]) * aa, space.decode(b[ch]) * ba);
+        out[ch] = space.encode((ca + t * (cb - ca)) / alpha);
+    }
+    out
+}
+
+/// `resize_exact` for straight-alpha images, filtered in premultiplied space so
+/// colour from fully transparent pixels does not bleed into edges as a halo.
+pub fn resize_rgba(
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+    filter: ResizeFilterType,
+) -> RgbaImage {
+    resize_rgba_in(image, width, height, filter, ColorSpace::Srgb)
+}
+
+/// [`resize_rgba`] with the filter applied in `space`.
+pub fn resize_rgba_in(
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+    filter: ResizeFilterType,
+    space: ColorSpace,
+) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+    if space == ColorSpace::Srgb && image.pixels().all(|p| p[3] == 255) {
+        return DynamicImage::ImageRgba8(image.clone())
+            .resize_exact(width, height, filter)
+            .to_rgba8();
+    }
+
+    let premultiplied = Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+        let p = image.get_pixel(x, y);
+        let a = p[3] as f32 / 255.0;
+        Rgba([
+            space.decode(p[0]) * a,
+            space.decode(p[1]) * a,
+            space.decode(p[2]) * a,
+            a,
+        ])
+    });
+    let resized = image::imageops::resize(&premultiplied, width, height, filter);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let p = resized.get_pixel(x, y);
+        let a = p[3].clamp(0.0, 1.0);
+        if a <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let unpremultiply = |c: f32| space.encode(c / a);
+        Rgba([
+            unpremultiply(p[0]),
+            unpremultiply(p[1]),
+            unpremultiply(p[2]),
+            (a * 255.0).round() as u8,
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_blur_preserves_flat_signal() {
+        let data = vec![7.0f32; 5 * 4 * 3];
+        let blurred = box_blur(&data, 5, 4, 3, 2);
+        assert!(blurred.iter().all(|v| (v - 7.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_premultiplied_mix_and_resize_avoid_dark_fringes() {
+        let red = Rgba([255, 0, 0, 255]);
+        let clear = Rgba([0, 0, 0, 0]);
+        assert_eq!(
+            lerp_premultiplied(&red, &clear, 0.5),
+            Rgba([255, 0, 0, 128])
+        );
+
+        let half = RgbaImage::from_fn(8, 1, |x, _| if x < 4 { red } else { clear });
+        let resized = resize_rgba(&half, 16, 1, ResizeFilterType::Triangle);
+        for p in resized.pixels().filter(|p| p[3] > 0) {
+            assert_eq!(p[0], 255);
+        }
+    }
+
+    #[test]
+    fn test_linear_light_mix_is_brighter_and_round_trips() {
+        for v in 0..=255u8 {
+            assert_eq!(ColorSpace::Linear.encode(ColorSpace::Linear.decode(v)), v);
+        }
+
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let srgb = lerp_premultiplied_in(&black, &white, 0.5, ColorSpace::Srgb);
+        let linear = lerp_premultiplied_in(&black, &white, 0.5, ColorSpace::Linear);
+        assert_eq!(srgb[0], 128);
+        assert_eq!(linear[0], 188);
+    }
+}
@@ -0,0 +1,172 @@
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct SeedPoint {
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Limits background removal to key-coloured regions reachable from the border or
+/// from seed points, so enclosed key-coloured parts of the subject survive.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FloodFillOptions {
+    /// Start the fill from every key-coloured border pixel.
+    pub from_border: bool,
+    /// Extra starting points in merged-image pixels.
+    pub seeds: Vec<SeedPoint>,
+    /// Also remove enclosed key-coloured holes of at least this many pixels.
+    pub min_hole_area: Option<u32>,
+}
+
+impl Default for FloodFillOptions {
+    fn default() -> Self {
+        Self {
+            from_border: true,
+            seeds: Vec::new(),
+            min_hole_area: None,
+        }
+    }
+}
+
+/// Scanline flood fill over `keyable` from `(x, y)`, marking reached pixels in
+/// `filled`. Returns the filled row spans as inclusive index ranges.
+pub(crate) fn scanline_fill(
+    keyable: &[bool],
+    filled: &mut [bool],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+) -> Vec<(usize, usize)> {
+    let fillable = |filled: &[bool], idx: usize| keyable[idx] && !filled[idx];
+    if !fillable(filled, y * width + x) {
+        return Vec::new();
+    }
+
+    let mut spans = Vec::new();
+    let mut stack = vec![(x, y)];
+    while let Some((x, y)) = stack.pop() {
+        let row = y * width;
+        if !fillable(filled, row + x) {
+            continue;
+        }
+        let mut left = x;
+        while left > 0 && fillable(filled, row + left - 1) {
+            left -= 1;
+        }
+        let mut right = x;
+        while right + 1 < width && fillable(filled, row + right + 1) {
+            right += 1;
+        }
+        filled[row + left..=row + right].fill(true);
+        spans.push((row + left, row + right));
+
+        // Queue one seed per run of fillable pixels in the rows above and below.
+        for ny in [y.wrapping_sub(1), y + 1] {
+            if ny >= height {
+                continue;
+            }
+            let nrow = ny * width;
+            let mut in_run = false;
+            for nx in left..=right {
+                if fillable(filled, nrow + nx) {
+                    if !in_run {
+                        stack.push((nx, ny));
+                        in_run = true;
+                    }
+                } else {
+                    in_run = false;
+                }
+            }
+        }
+    }
+    spans
+}
+
+/// Pixels to remove: key-coloured pixels connected to the border or seeds, plus
+/// enclosed key-coloured regions of at least `min_hole_area` pixels.
+pub fn connected_key_mask(
+    keyable: &[bool],
+    width: u32,
+    height: u32,
+    options: &FloodFillOptions,
+) -> Vec<bool> {
+    let (w, h) = (width as usize, height as usize);
+    let mut filled = vec![false; keyable.len()];
+    if w == 0 || h == 0 {
+        return filled;
+    }
+
+    if options.from_border {
+        for x in 0..w {
+            scanline_fill(keyable, &mut filled, w, h, x, 0);
+            scanline_fill(keyable, &mut filled, w, h, x, h - 1);
+        }
+        for y in 0..h {
+            scanline_fill(keyable, &mut filled, w, h, 0, y);
+            scanline_fill(keyable, &mut filled, w, h, w - 1, y);
+        }
+    }
+    for seed in &options.seeds {
+        if (seed.x as usize) < w && (seed.y as usize) < h {
+            scanline_fill(keyable, &mut filled, w, h, seed.x as usize, seed.y as usize);
+        }
+    }
+
+    if let Some(min_area) = options.min_hole_area {
+        // Every key pixel left unreached belongs to an enclosed hole; measure each one.
+        let mut visited = filled.clone();
+        for idx in 0..keyable.len() {
+            if !keyable[idx] || visited[idx] {
+                continue;
+            }
+            let spans = scanline_fill(keyable, &mut visited, w, h, idx % w, idx / w);
+            let area: usize = spans.iter().map(|(start, end)| end - start + 1).sum();
+            if area >= min_area as usize {
+                for (start, end) in spans {
+                    filled[start..=end].fill(true);
+                }
+            }
+        }
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_keeps_enclosed_key_regions_unless_large() {
+        // Key background around a ring-shaped subject with a 3x3 key hole inside,
+        // and a concave notch on the right that is still open to the border.
+        let (w, h) = (12usize, 10usize);
+        let keyable: Vec<bool> = (0..w * h)
+            .map(|idx| {
+                let (x, y) = (idx % w, idx / w);
+                let subject = (2..9).contains(&x) && (2..8).contains(&y);
+                let hole = (4..7).contains(&x) && (4..7).contains(&y);
+                let notch = (7..9).contains(&x) && y == 3;
+                !subject || hole || notch
+            })
+            .collect();
+
+        let border = connected_key_mask(&keyable, w as u32, h as u32, &FloodFillOptions::default());
+        assert!(border[0] && border[w * 3 + 7]);
+        assert!(!border[w * 5 + 5]);
+        assert!(!border[w * 2 + 2]);
+
+        let options = FloodFillOptions {
+            min_hole_area: Some(9),
+            ..FloodFillOptions::default()
+        };
+        assert!(connected_key_mask(&keyable, w as u32, h as u32, &options)[w * 5 + 5]);
+
+        let seeded = FloodFillOptions {
+            from_border: false,
+            seeds: vec![SeedPoint { x: 5, y: 5 }],
+            min_hole_area: None,
+        };
+        let mask = connected_key_mask(&keyable, w as u32, h as u32, &seeded);
+        assert!(mask[w * 5 + 5] && !mask[0]);
+    }
+}
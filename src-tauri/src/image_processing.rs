@@ -1,12 +1,22 @@
+use crate::background_model::{BackgroundModel, BackgroundModelOptions};
+use crate::dual_background::{has_dual_background_pair, solve_dual_tile};
+use crate::fidelity::{guard_subject, transfer_detail, DetailTransferOptions, SubjectGuardOptions};
+use crate::filters::{lerp_premultiplied_in, resize_rgba, resize_rgba_in, ColorSpace};
+use crate::flood_fill::{connected_key_mask, FloodFillOptions};
+use crate::keying::{KeyMatcher, SoftKeyOptions};
+use crate::matting::{refine_alpha, EdgeRefineOptions};
+use crate::morphology::{clean_alpha, AlphaCleanupOptions};
+use crate::region_mask::{MaskIntent, RegionMask, REGION_MASK_FILE};
+use crate::shadow::{extract_shadow, shadow_layer, ShadowOptions, SHADOW_LAYER_FILE};
 use base64::{engine::general_purpose, Engine as _};
 use exif::{In, Tag};
 use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
 use image::imageops::{crop_imm, FilterType as ResizeFilterType};
-use image::{ColorType, DynamicImage, ImageEncoder, Rgba, RgbaImage};
+use image::{ColorType, DynamicImage, GrayImage, ImageEncoder, Rgba, RgbaImage};
 use rayon::prelude::*;
 use std::io::{BufWriter, Cursor};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn open_image_with_orientation(path: &str) -> Result<DynamicImage, String> {
     let mut img = image::open(path).map_err(|e| e.to_string())?;
@@ -50,12 +60,12 @@ fn save_png_fast(path: &Path, image: &RgbaImage) -> Result<(), String> {
 }
 
 #[derive(Clone, Copy, Debug)]
-enum ImageFileFormat {
+pub enum ImageFileFormat {
     Png,
     Jpeg,
 }
 
-fn image_format_from_path(path: &Path) -> ImageFileFormat {
+pub(crate) fn image_format_from_path(path: &Path) -> ImageFileFormat {
     match path
         .extension()
         .and_then(|ext| ext.to_str())
@@ -68,7 +78,7 @@ fn image_format_from_path(path: &Path) -> ImageFileFormat {
     }
 }
 
-fn file_extension(format: ImageFileFormat) -> &'static str {
+pub fn file_extension(format: ImageFileFormat) -> &'static str {
     match format {
         ImageFileFormat::Png => "png",
         ImageFileFormat::Jpeg => "jpg",
@@ -98,7 +108,7 @@ fn save_jpeg_fast(path: &Path, image: &RgbaImage, quality: u8) -> Result<(), Str
         .map_err(|e| e.to_string())
 }
 
-fn save_image_fast(path: &Path, image: &RgbaImage, format: ImageFileFormat) -> Result<(), String> {
+pub fn save_image_fast(path: &Path, image: &RgbaImage, format: ImageFileFormat) -> Result<(), String> {
     match format {
         ImageFileFormat::Png => save_png_fast(path, image),
         ImageFileFormat::Jpeg => save_jpeg_fast(path, image, 90),
@@ -113,7 +123,7 @@ pub fn save_rgba_image_auto(path: &str, image: &RgbaImage) -> Result<(), String>
     save_image_fast_auto(Path::new(path), image)
 }
 
-fn encode_png_data_url_fast(image: &RgbaImage) -> Result<String, String> {
+pub fn encode_png_data_url_fast(image: &RgbaImage) -> Result<String, String> {
     let mut buffer = Cursor::new(Vec::new());
     let encoder =
         PngEncoder::new_with_quality(&mut buffer, CompressionType::Fast, PngFilterType::NoFilter);
@@ -130,7 +140,7 @@ fn encode_png_data_url_fast(image: &RgbaImage) -> Result<String, String> {
     Ok(format!("data:image/png;base64,{}", b64))
 }
 
-fn encode_jpeg_data_url_fast(image: &RgbaImage, quality: u8) -> Result<String, String> {
+pub fn encode_jpeg_data_url_fast(image: &RgbaImage, quality: u8) -> Result<String, String> {
     let mut buffer = Cursor::new(Vec::new());
     let mut encoder = JpegEncoder::new_with_quality(&mut buffer, quality);
     let rgb = flatten_rgba_to_rgb_white(image);
@@ -141,6 +151,14 @@ fn encode_jpeg_data_url_fast(image: &RgbaImage, quality: u8) -> Result<String, S
     Ok(format!("data:image/jpeg;base64,{}", b64))
 }
 
+/// Decodes the base64 payload of a data URL (or a bare base64 string).
+pub fn decode_data_url(data: &str) -> Result<Vec<u8>, String> {
+    let payload = data.rsplit(',').next().unwrap_or(data);
+    general_purpose::STANDARD
+        .decode(payload)
+        .map_err(|e| e.to_string())
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct TileInfo {
     pub r: u32,
@@ -153,26 +171,175 @@ pub struct TileInfo {
     pub original_path: String,
 }
 
-// Helper: Check if pixel matches key color.
-fn is_key_color(p: &Rgba<u8>, color: &str, tolerance: u8) -> bool {
-    if p[3] < 10 {
-        return true;
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MergeOptions {
+    pub layout: TileLayout,
+    pub detail_transfer: Option<DetailTransferOptions>,
+    pub subject_guard: Option<SubjectGuardOptions>,
+    /// Fill for pixels no tile covers (e.g. outside a subject ROI). Defaults to
+    /// transparency when removing the background and to the key colour otherwise.
+    pub uncovered_fill: Option<UncoveredFill>,
+    /// Space overlaps are blended and mismatched tiles resized in.
+    pub color_space: ColorSpace,
+    /// Anti-aliased keying with despill instead of the binary key colour cut-out.
+    pub soft_key: Option<SoftKeyOptions>,
+    /// Re-fits keyed edges to the original photo with a guided filter.
+    pub edge_refine: Option<EdgeRefineOptions>,
+    /// Tiles were generated on white and on black; alpha is solved from each pair
+    /// instead of keyed, and the result keeps it.
+    pub dual_background: bool,
+    /// Only remove key colour reachable from the border or seed points.
+    pub flood_fill: Option<FloodFillOptions>,
+    /// Morphology, island and hole cleanup of the keyed alpha.
+    pub alpha_cleanup: Option<AlphaCleanupOptions>,
+    /// Key against a fitted smooth backdrop instead of a constant colour.
+    pub background_model: Option<BackgroundModelOptions>,
+    /// Keep cast shadows on the backdrop as semi-transparent black.
+    pub shadow: Option<ShadowOptions>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum UncoveredFill {
+    KeyColor,
+    Transparent,
+}
+
+pub const SUBJECT_GUARD_MASK_FILE: &str = "subject_guard_mask.png";
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TileLayout {
+    /// Aligned rows and columns; four tiles meet at every interior corner.
+    #[default]
+    Grid,
+    /// Odd rows are shifted by half a stride so at most three tiles meet at any point.
+    Brick,
+}
+
+/// Tile size, overlap and stride of a uniform `rows x cols` grid.
+#[derive(Clone, Copy, Debug)]
+pub struct GridGeometry {
+    pub width: u32,
+    pub height: u32,
+    pub rows: u32,
+    pub cols: u32,
+    pub layout: TileLayout,
+    pub tile_w: u32,
+    pub tile_h: u32,
+    pub overlap_w: u32,
+    pub overlap_h: u32,
+    pub stride_w: u32,
+    pub stride_h: u32,
+}
+
+impl GridGeometry {
+    pub fn new(
+        width: u32,
+        height: u32,
+        rows: u32,
+        cols: u32,
+        overlap_ratio_x: f64,
+        overlap_ratio_y: f64,
+    ) -> Result<Self, String> {
+        let denom_w = cols as f64 - (cols as f64 - 1.0) * overlap_ratio_x;
+        let denom_h = rows as f64 - (rows as f64 - 1.0) * overlap_ratio_y;
+        if denom_w <= 0.0 || denom_h <= 0.0 {
+            return Err("Invalid overlap/grid configuration".to_string());
+        }
+
+        let tile_w = (width as f64 / denom_w).ceil() as u32;
+        let tile_h = (height as f64 / denom_h).ceil() as u32;
+        let overlap_w = (tile_w as f64 * overlap_ratio_x) as u32;
+        let overlap_h = (tile_h as f64 * overlap_ratio_y) as u32;
+        Ok(Self {
+            width,
+            height,
+            rows,
+            cols,
+            layout: TileLayout::Grid,
+            tile_w,
+            tile_h,
+            overlap_w,
+            overlap_h,
+            stride_w: tile_w.saturating_sub(overlap_w).max(1),
+            stride_h: tile_h.saturating_sub(overlap_h).max(1),
+        })
+    }
+
+    pub fn with_layout(mut self, layout: TileLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    fn is_shifted_row(&self, r: u32) -> bool {
+        self.layout == TileLayout::Brick && r % 2 == 1 && self.cols > 1
+    }
+
+    /// Number of tiles in row `r`. Shifted brick rows carry one extra, clipped tile.
+    pub fn row_len(&self, r: u32) -> u32 {
+        if self.is_shifted_row(r) {
+            self.cols + 1
+        } else {
+            self.cols
+        }
+    }
+
+    /// All `(r, c)` keys of the layout in row-major order.
+    pub fn tile_keys(&self) -> Vec<(u32, u32)> {
+        (0..self.rows)
+            .flat_map(|r| (0..self.row_len(r)).map(move |c| (r, c)))
+            .collect()
+    }
+
+    /// Rectangle `(x, y, width, height)` of tile `r, c`, clipped to the image.
+    pub fn tile_rect(&self, r: u32, c: u32) -> Option<(u32, u32, u32, u32)> {
+        let y = (r * self.stride_h).min(self.height.saturating_sub(1));
+        let actual_h = self.tile_h.min(self.height - y);
+
+        let (x, actual_w) = if self.is_shifted_row(r) {
+            let start = c as i64 * self.stride_w as i64 - (self.stride_w / 2) as i64;
+            let end = (start + self.tile_w as i64).min(self.width as i64);
+            let start = start.max(0);
+            if end <= start {
+                return None;
+            }
+            (start as u32, (end - start) as u32)
+        } else {
+            let x = (c * self.stride_w).min(self.width.saturating_sub(1));
+            (x, self.tile_w.min(self.width - x))
+        };
+
+        if actual_w == 0 || actual_h == 0 {
+            return None;
+        }
+        Some((x, y, actual_w, actual_h))
     }
+}
 
-    let white_min = 240u8.saturating_sub(tolerance);
-    let black_max = 15u8.saturating_add(tolerance);
+fn find_original_tile(dir: &Path, r: u32, c: u32) -> Option<PathBuf> {
+    ["png", "jpg", "jpeg"]
+        .iter()
+        .map(|ext| dir.join(format!("orig_tile_{}_{}.{}", r, c, ext)))
+        .find(|candidate| candidate.exists())
+}
 
-    let color_min = 240u8.saturating_sub(tolerance);
-    let color_max = 50u8.saturating_add(tolerance);
+fn find_original_source(dir: &Path) -> Option<PathBuf> {
+    ["png", "jpg", "jpeg"]
+        .iter()
+        .map(|ext| dir.join(format!("original_source.{}", ext)))
+        .find(|candidate| candidate.exists())
+}
 
-    match color {
-        "white" => p[0] >= white_min && p[1] >= white_min && p[2] >= white_min,
-        "black" => p[0] <= black_max && p[1] <= black_max && p[2] <= black_max,
-        "red" => p[0] >= color_min && p[1] <= color_max && p[2] <= color_max,
-        "blue" => p[0] <= color_max && p[1] <= color_max && p[2] >= color_min,
-        "green" => p[0] <= color_max && p[1] >= color_min && p[2] <= color_max,
-        _ => p[0] >= white_min && p[1] >= white_min && p[2] >= white_min,
+fn load_original_tile(original_path: &Path, width: u32, height: u32) -> Result<RgbaImage, String> {
+    let mut original = image::open(original_path)
+        .map_err(|e| format!("Failed to open {}: {}", original_path.display(), e))?
+        .to_rgba8();
+    if original.width() != width || original.height() != height {
+        original = resize_rgba(&original, width, height, ResizeFilterType::Lanczos3);
     }
+    Ok(original)
 }
 
 pub fn crop_image(
@@ -199,10 +366,10 @@ pub fn crop_image(
 }
 
 pub fn save_resized_tile(path: &str, data: &[u8], width: u32, height: u32) -> Result<(), String> {
-    let img = image::load_from_memory(data).map_err(|e| e.to_string())?;
-    let resized = img
-        .resize_exact(width, height, ResizeFilterType::Lanczos3)
+    let img = image::load_from_memory(data)
+        .map_err(|e| e.to_string())?
         .to_rgba8();
+    let resized = resize_rgba(&img, width, height, ResizeFilterType::Lanczos3);
     save_image_fast_auto(Path::new(path), &resized)
 }
 
@@ -236,21 +403,15 @@ pub fn load_image_region_data_url(
     }
 }
 
-pub fn split_image(
+/// Opens the input with EXIF orientation applied and stores a copy in `output_dir`.
+///
+/// Returns the decoded image, the tile file format and the path of the stored copy.
+pub fn prepare_split_source(
     input_path: &str,
-    rows: u32,
-    cols: u32,
-    overlap_ratio_x: f64,
-    overlap_ratio_y: f64,
     prefer_jpeg: bool,
     output_dir: &Path,
-) -> Result<(Vec<TileInfo>, u32, u32, String), String> {
-    if rows == 0 || cols == 0 {
-        return Err("Rows and cols must be greater than zero".to_string());
-    }
-
+) -> Result<(RgbaImage, ImageFileFormat, String), String> {
     let img_rgba = open_image_with_orientation(input_path)?.to_rgba8();
-    let (w, h) = img_rgba.dimensions();
     let image_format = if prefer_jpeg {
         ImageFileFormat::Jpeg
     } else {
@@ -263,43 +424,29 @@ pub fn split_image(
     save_image_fast(&original_copy_path, &img_rgba, image_format)?;
     let new_input_path = original_copy_path.to_string_lossy().to_string();
 
-    let denom_w = cols as f64 - (cols as f64 - 1.0) * overlap_ratio_x;
-    let denom_h = rows as f64 - (rows as f64 - 1.0) * overlap_ratio_y;
-    if denom_w <= 0.0 || denom_h <= 0.0 {
-        return Err("Invalid overlap/grid configuration".to_string());
-    }
-
-    let tile_w = (w as f64 / denom_w).ceil() as u32;
-    let tile_h = (h as f64 / denom_h).ceil() as u32;
-    let overlap_w = (tile_w as f64 * overlap_ratio_x) as u32;
-    let overlap_h = (tile_h as f64 * overlap_ratio_y) as u32;
-    let stride_w = tile_w.saturating_sub(overlap_w).max(1);
-    let stride_h = tile_h.saturating_sub(overlap_h).max(1);
-
-    let mut tile_configs = Vec::with_capacity((rows * cols) as usize);
-    for r in 0..rows {
-        for c in 0..cols {
-            let x = (c * stride_w).min(w.saturating_sub(1));
-            let y = (r * stride_h).min(h.saturating_sub(1));
-            let actual_w = tile_w.min(w - x);
-            let actual_h = tile_h.min(h - y);
-            if actual_w == 0 || actual_h == 0 {
-                continue;
-            }
-            tile_configs.push((r, c, x, y, actual_w, actual_h));
-        }
-    }
+    Ok((img_rgba, image_format, new_input_path))
+}
 
-    let tiles: Result<Vec<TileInfo>, String> = tile_configs
+/// Crops each `(r, c, x, y, width, height)` config out of `image` and saves it as
+/// `orig_{prefix}tile_{r}_{c}`. The processed result is expected at `{prefix}tile_{r}_{c}`.
+pub fn write_tile_files(
+    image: &RgbaImage,
+    tile_configs: Vec<(u32, u32, u32, u32, u32, u32)>,
+    image_format: ImageFileFormat,
+    output_dir: &Path,
+    prefix: &str,
+) -> Result<Vec<TileInfo>, String> {
+    let ext = file_extension(image_format);
+    tile_configs
         .into_par_iter()
         .map(|(r, c, x, y, actual_w, actual_h)| {
-            let tile = crop_imm(&img_rgba, x, y, actual_w, actual_h).to_image();
+            let tile = crop_imm(image, x, y, actual_w, actual_h).to_image();
 
-            let orig_file_name = format!("orig_tile_{}_{}.{}", r, c, ext);
+            let orig_file_name = format!("orig_{}tile_{}_{}.{}", prefix, r, c, ext);
             let orig_file_path = output_dir.join(&orig_file_name);
             save_image_fast(&orig_file_path, &tile, image_format)?;
 
-            let proc_file_name = format!("tile_{}_{}.{}", r, c, ext);
+            let proc_file_name = format!("{}tile_{}_{}.{}", prefix, r, c, ext);
             let proc_file_path = output_dir.join(&proc_file_name);
 
             Ok(TileInfo {
@@ -313,9 +460,392 @@ pub fn split_image(
                 original_path: orig_file_path.to_string_lossy().to_string(),
             })
         })
+        .collect()
+}
+
+pub fn split_image(
+    input_path: &str,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    layout: TileLayout,
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<(Vec<TileInfo>, u32, u32, String), String> {
+    if rows == 0 || cols == 0 {
+        return Err("Rows and cols must be greater than zero".to_string());
+    }
+
+    let (img_rgba, image_format, new_input_path) =
+        prepare_split_source(input_path, prefer_jpeg, output_dir)?;
+    let (w, h) = img_rgba.dimensions();
+
+    let grid = GridGeometry::new(w, h, rows, cols, overlap_ratio_x, overlap_ratio_y)?
+        .with_layout(layout);
+
+    let tile_configs: Vec<_> = grid
+        .tile_keys()
+        .into_iter()
+        .filter_map(|(r, c)| {
+            let (x, y, actual_w, actual_h) = grid.tile_rect(r, c)?;
+            Some((r, c, x, y, actual_w, actual_h))
+        })
         .collect();
 
-    Ok((tiles?, w, h, new_input_path))
+    let tiles = write_tile_files(&img_rgba, tile_configs, image_format, output_dir, "")?;
+    Ok((tiles, w, h, new_input_path))
+}
+
+struct TileJob {
+    r: u32,
+    c: u32,
+    start_x: u32,
+    start_y: u32,
+    expected_w: u32,
+    expected_h: u32,
+    path: String,
+    original_path: Option<PathBuf>,
+}
+
+struct LoadedTile {
+    r: u32,
+    c: u32,
+    start_x: u32,
+    start_y: u32,
+    image: RgbaImage,
+    guard_mask: Option<GrayImage>,
+}
+
+/// Settings and session state shared by the tile load pass and the final keying pass.
+pub(crate) struct MergeContext<'a> {
+    width: u32,
+    height: u32,
+    key: KeyMatcher,
+    remove_bg: bool,
+    options: &'a MergeOptions,
+    session_dir: PathBuf,
+    region_mask: Option<RegionMask>,
+}
+
+impl<'a> MergeContext<'a> {
+    pub(crate) fn new(
+        first_tile_path: &str,
+        width: u32,
+        height: u32,
+        key_color: &str,
+        remove_bg: bool,
+        tolerance: u8,
+        options: &'a MergeOptions,
+    ) -> Result<Self, String> {
+        let session_dir = Path::new(first_tile_path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let region_mask_path = session_dir.join(REGION_MASK_FILE);
+        let region_mask = if region_mask_path.is_file() {
+            Some(RegionMask::load(&region_mask_path, width, height)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            width,
+            height,
+            key: KeyMatcher::parse(key_color, tolerance)?,
+            remove_bg,
+            options,
+            session_dir,
+            region_mask,
+        })
+    }
+
+    fn is_key(&self, p: &Rgba<u8>) -> bool {
+        self.key.is_key(p)
+    }
+
+    fn load_tiles(&self, jobs: Vec<TileJob>) -> Result<Vec<LoadedTile>, String> {
+        jobs.into_par_iter()
+            .map(|job| self.load_tile(job))
+            .collect()
+    }
+
+    fn load_tile(&self, job: TileJob) -> Result<LoadedTile, String> {
+        let options = self.options;
+        let original_path = job.original_path.clone().or_else(|| {
+            Path::new(&job.path)
+                .parent()
+                .and_then(|dir| find_original_tile(dir, job.r, job.c))
+        });
+
+        let dual = options.dual_background && has_dual_background_pair(&job.path);
+        let mut final_path = job.path.clone();
+        let mut is_original = false;
+        if !dual && !Path::new(&job.path).exists() {
+            if let Some(fallback) = original_path.as_ref().filter(|p| p.exists()) {
+                final_path = fallback.to_string_lossy().to_string();
+                is_original = true;
+            } else {
+                return Err(format!(
+                    "Tile result and original both missing for {},{}",
+                    job.r, job.c
+                ));
+            }
+        }
+
+        let mut img = if dual {
+            solve_dual_tile(&job.path, job.expected_w, job.expected_h)?
+        } else {
+            image::open(&final_path)
+                .map_err(|e| format!("Failed to open {}: {}", final_path, e))?
+                .to_rgba8()
+        };
+
+        if img.width() != job.expected_w || img.height() != job.expected_h {
+            img = resize_rgba_in(
+                &img,
+                job.expected_w,
+                job.expected_h,
+                ResizeFilterType::Lanczos3,
+                options.color_space,
+            );
+        }
+
+        let needs_original = options.detail_transfer.is_some() || options.subject_guard.is_some();
+        let original = match original_path.as_ref().filter(|p| p.exists()) {
+            Some(path) if needs_original && !is_original => {
+                Some(load_original_tile(path, img.width(), img.height())?)
+            }
+            _ => None,
+        };
+
+        // Pixels the user forced to AI output must survive the fidelity passes untouched.
+        let forced_ai = self
+            .region_mask
+            .as_ref()
+            .filter(|mask| {
+                original.is_some()
+                    && mask.has_intent_in(
+                        MaskIntent::Force,
+                        job.start_x,
+                        job.start_y,
+                        img.width(),
+                        img.height(),
+                    )
+            })
+            .map(|_| img.clone());
+
+        let mut guard_mask = None;
+        if let Some(original) = original.as_ref() {
+            if let Some(guard) = options.subject_guard.as_ref() {
+                guard_mask = Some(guard_subject(&mut img, original, guard, |p| self.is_key(p)));
+            }
+            if let Some(detail) = options.detail_transfer.as_ref() {
+                transfer_detail(&mut img, original, detail, |p| {
+                    self.remove_bg && self.is_key(p)
+                });
+            }
+        }
+
+        if let (Some(mask), Some(ai)) = (self.region_mask.as_ref(), forced_ai.as_ref()) {
+            for (x, y, px) in img.enumerate_pixels_mut() {
+                if mask.intent(job.start_x + x, job.start_y + y) == MaskIntent::Force {
+                    *px = *ai.get_pixel(x, y);
+                    if let Some(tile_mask) = guard_mask.as_mut() {
+                        tile_mask.put_pixel(x, y, image::Luma([0]));
+                    }
+                }
+            }
+        }
+
+        Ok(LoadedTile {
+            r: job.r,
+            c: job.c,
+            start_x: job.start_x,
+            start_y: job.start_y,
+            image: img,
+            guard_mask,
+        })
+    }
+
+    /// Writes the merged subject guard mask and returns it for the keying pass.
+    fn write_guard_mask(&self, loaded_tiles: &[LoadedTile]) -> Result<Option<GrayImage>, String> {
+        if self.options.subject_guard.is_none() {
+            // Drop a mask left over from an earlier guarded merge so it is not exported.
+            let _ = std::fs::remove_file(self.session_dir.join(SUBJECT_GUARD_MASK_FILE));
+            return Ok(None);
+        }
+
+        let mut mask = GrayImage::new(self.width, self.height);
+        for tile in loaded_tiles {
+            let Some(tile_mask) = tile.guard_mask.as_ref() else {
+                continue;
+            };
+            for (x, y, value) in tile_mask.enumerate_pixels() {
+                let (gx, gy) = (tile.start_x + x, tile.start_y + y);
+                if gx < self.width && gy < self.height && value[0] > mask.get_pixel(gx, gy)[0] {
+                    mask.put_pixel(gx, gy, *value);
+                }
+            }
+        }
+        mask.save(self.session_dir.join(SUBJECT_GUARD_MASK_FILE)).map_err(|e| e.to_string())?;
+        Ok(Some(mask))
+    }
+
+    fn write_shadow_layer(&self, shadow: Option<&[u8]>) -> Result<(), String> {
+        let path = self.session_dir.join(SHADOW_LAYER_FILE);
+        match shadow {
+            Some(shadow) => shadow_layer(shadow, self.width, self.height)
+                .save(&path)
+                .map_err(|e| e.to_string()),
+            None => {
+                // Drop a layer left over from an earlier merge so it is not exported.
+                let _ = std::fs::remove_file(&path);
+                Ok(())
+            }
+        }
+    }
+
+    /// The session's original source at the merged size.
+    fn load_source(&self, purpose: &str) -> Result<RgbaImage, String> {
+        let source_path = find_original_source(&self.session_dir)
+            .ok_or_else(|| format!("Original source is required to {}", purpose))?;
+        let source = image::open(&source_path)
+            .map_err(|e| format!("Failed to open {}: {}", source_path.display(), e))?
+            .to_rgba8();
+        Ok(resize_rgba_in(
+            &source,
+            self.width,
+            self.height,
+            ResizeFilterType::Lanczos3,
+            self.options.color_space,
+        ))
+    }
+
+    /// Applies the protect mask and background removal, then encodes the result.
+    /// `guard_mask` marks subject pixels the shadow pass must leave alone.
+    pub(crate) fn finish(
+        &self,
+        mut final_img: RgbaImage,
+        guard_mask: Option<&GrayImage>,
+    ) -> Result<String, String> {
+        let (original_w, original_h) = (self.width, self.height);
+        let region_mask = self.region_mask.as_ref();
+
+        if let Some(mask) = region_mask {
+            if mask.has_intent_in(MaskIntent::Protect, 0, 0, original_w, original_h) {
+                let source = self.load_source("apply the protect mask")?;
+                for (x, y, px) in final_img.enumerate_pixels_mut() {
+                    if mask.intent(x, y) == MaskIntent::Protect {
+                        *px = *source.get_pixel(x, y);
+                    }
+                }
+            }
+        }
+
+        let mut shadow: Option<Vec<u8>> = None;
+        if self.remove_bg {
+            let soft_key = self.options.soft_key.as_ref();
+            let background = self
+                .options
+                .background_model
+                .as_ref()
+                .and_then(|options| BackgroundModel::fit(&final_img, &self.key, options))
+                .map(|model| model.render());
+            let soft_key_at = |idx: usize, p: &Rgba<u8>, options: &SoftKeyOptions| {
+                match background.as_ref() {
+                    Some(background) => self.key.soft_key_near(p, background[idx], options),
+                    None => self.key.soft_key(p, options),
+                }
+            };
+            let is_protected = |idx: usize| {
+                region_mask.is_some_and(|mask| {
+                    let idx = idx as u32;
+                    mask.intent(idx % original_w, idx / original_w) == MaskIntent::Protect
+                })
+            };
+            // Pixels the keyer would change: key colour, or inside the soft keyer's ramp.
+            let keyable: Vec<bool> = final_img
+                .as_raw()
+                .par_chunks_exact(4)
+                .enumerate()
+                .map(|(idx, pixel)| {
+                    let p = Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    !is_protected(idx)
+                        && match (soft_key, background.as_ref()) {
+                            (Some(soft_key), _) => p[3] < 10 || soft_key_at(idx, &p, soft_key) != p,
+                            (None, Some(background)) => self.key.is_key_near(&p, background[idx]),
+                            (None, None) => self.is_key(&p),
+                        }
+                })
+                .collect();
+            let remove = match self.options.flood_fill.as_ref() {
+                Some(fill) => connected_key_mask(&keyable, original_w, original_h, fill),
+                None => keyable,
+            };
+            // Cast shadows are measured against the backdrop before keying touches them.
+            if let Some(options) = self.options.shadow.as_ref() {
+                let fill = self.key.fill_rgb();
+                let backdrop = |idx: usize| Some(background.as_ref().map_or(fill, |bg| bg[idx]));
+                let guarded = |idx: usize| guard_mask.is_some_and(|mask| mask.as_raw()[idx] > 0);
+                let subject: Vec<bool> = (0..remove.len())
+                    .map(|idx| is_protected(idx) || guarded(idx))
+                    .collect();
+                shadow = Some(extract_shadow(
+                    &final_img, &remove, &subject, backdrop, options,
+                ));
+            }
+            let casts_shadow = |idx: usize| shadow.as_ref().is_some_and(|s| s[idx] > 0);
+
+            final_img
+                .as_flat_samples_mut()
+                .as_mut_slice()
+                .par_chunks_exact_mut(4)
+                .zip(remove.par_iter())
+                .enumerate()
+                .for_each(|(idx, (pixel, &remove))| {
+                    if casts_shadow(idx) {
+                        pixel.fill(0);
+                        return;
+                    }
+                    if !remove {
+                        return;
+                    }
+                    let p = Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    match soft_key {
+                        Some(soft_key) => pixel.copy_from_slice(&soft_key_at(idx, &p, soft_key).0),
+                        None => pixel.fill(0),
+                    }
+                });
+
+            if let Some(refine) = self.options.edge_refine.as_ref() {
+                let source = self.load_source("refine keyed edges")?;
+                refine_alpha(&mut final_img, &source, refine);
+            }
+        }
+
+        if self.remove_bg || self.options.dual_background {
+            if let Some(cleanup) = self.options.alpha_cleanup.as_ref() {
+                clean_alpha(&mut final_img, cleanup);
+            }
+            // Shadow goes under the subject, so pixels refined back into it stay.
+            if let Some(shadow) = shadow.as_mut() {
+                for (px, alpha) in final_img.pixels_mut().zip(shadow.iter_mut()) {
+                    if *alpha > 0 && px[3] == 0 {
+                        *px = Rgba([0, 0, 0, *alpha]);
+                    } else {
+                        *alpha = 0;
+                    }
+                }
+            }
+            self.write_shadow_layer(shadow.as_deref())?;
+            encode_png_data_url_fast(&final_img)
+        } else {
+            self.write_shadow_layer(None)?;
+            encode_jpeg_data_url_fast(&final_img, 90)
+        }
+    }
 }
 
 pub fn merge_tiles(
@@ -327,6 +857,7 @@ pub fn merge_tiles(
     key_color: &str,
     remove_bg: bool,
     tolerance: u8,
+    options: &MergeOptions,
 ) -> Result<String, String> {
     if tile_paths.is_empty() {
         return Err("No tiles to merge".to_string());
@@ -334,56 +865,46 @@ pub fn merge_tiles(
     if original_w == 0 || original_h == 0 {
         return Err("Invalid original image dimensions".to_string());
     }
+    // Dual-background tiles already carry their alpha.
+    let remove_bg = remove_bg && !options.dual_background;
 
     let max_r = tile_paths.iter().map(|(r, _, _)| *r).max().unwrap_or(0);
-    let max_c = tile_paths.iter().map(|(_, c, _)| *c).max().unwrap_or(0);
+    // Shifted brick rows hold one extra tile, so the column count comes from even rows.
+    let max_c = tile_paths
+        .iter()
+        .filter(|(r, _, _)| options.layout == TileLayout::Grid || r % 2 == 0)
+        .map(|(_, c, _)| *c)
+        .max()
+        .unwrap_or(0);
     let rows = max_r + 1;
     let cols = max_c + 1;
 
-    let denom_w = cols as f64 - (cols as f64 - 1.0) * overlap_ratio_x;
-    let denom_h = rows as f64 - (rows as f64 - 1.0) * overlap_ratio_y;
-    if denom_w <= 0.0 || denom_h <= 0.0 {
-        return Err("Invalid overlap/grid configuration".to_string());
-    }
-
-    let tile_w = (original_w as f64 / denom_w).ceil() as u32;
-    let tile_h = (original_h as f64 / denom_h).ceil() as u32;
-    let overlap_w = (tile_w as f64 * overlap_ratio_x) as u32;
-    let overlap_h = (tile_h as f64 * overlap_ratio_y) as u32;
-    let stride_w = tile_w.saturating_sub(overlap_w).max(1);
-    let stride_h = tile_h.saturating_sub(overlap_h).max(1);
-
-    #[derive(Debug)]
-    struct TileJob {
-        r: u32,
-        c: u32,
-        start_x: u32,
-        start_y: u32,
-        expected_w: u32,
-        expected_h: u32,
-        path: String,
-    }
-
-    #[derive(Debug)]
-    struct LoadedTile {
-        r: u32,
-        c: u32,
-        start_x: u32,
-        start_y: u32,
-        image: RgbaImage,
-    }
+    let grid = GridGeometry::new(
+        original_w,
+        original_h,
+        rows,
+        cols,
+        overlap_ratio_x,
+        overlap_ratio_y,
+    )?
+    .with_layout(options.layout);
+    let overlap_w = grid.overlap_w;
+    let overlap_h = grid.overlap_h;
+
+    let ctx = MergeContext::new(
+        &tile_paths[0].2,
+        original_w,
+        original_h,
+        key_color,
+        remove_bg,
+        tolerance,
+        options,
+    )?;
 
     let jobs: Vec<TileJob> = tile_paths
         .into_iter()
         .filter_map(|(r, c, path)| {
-            let start_x = (c * stride_w).min(original_w.saturating_sub(1));
-            let start_y = (r * stride_h).min(original_h.saturating_sub(1));
-
-            let expected_w = tile_w.min(original_w - start_x);
-            let expected_h = tile_h.min(original_h - start_y);
-            if expected_w == 0 || expected_h == 0 {
-                return None;
-            }
+            let (start_x, start_y, expected_w, expected_h) = grid.tile_rect(r, c)?;
 
             Some(TileJob {
                 r,
@@ -393,6 +914,7 @@ pub fn merge_tiles(
                 expected_w,
                 expected_h,
                 path,
+                original_path: None,
             })
         })
         .collect();
@@ -401,51 +923,9 @@ pub fn merge_tiles(
         return Err("No valid tiles to merge".to_string());
     }
 
-    let mut loaded_tiles: Vec<LoadedTile> = jobs
-        .into_par_iter()
-        .map(|job| {
-            let mut final_path = job.path.clone();
-            if !Path::new(&job.path).exists() {
-                let dir = Path::new(&job.path).parent().ok_or("Invalid tile path")?;
-                let mut fallback_path = None;
-                for ext in ["png", "jpg", "jpeg"] {
-                    let candidate = dir.join(format!("orig_tile_{}_{}.{}", job.r, job.c, ext));
-                    if candidate.exists() {
-                        fallback_path = Some(candidate);
-                        break;
-                    }
-                }
-                if let Some(fallback) = fallback_path {
-                    final_path = fallback.to_string_lossy().to_string();
-                } else {
-                    return Err(format!(
-                        "Tile result and original both missing for {},{}",
-                        job.r, job.c
-                    ));
-                }
-            }
-
-            let mut img = image::open(&final_path)
-                .map_err(|e| format!("Failed to open {}: {}", final_path, e))?
-                .to_rgba8();
-
-            if img.width() != job.expected_w || img.height() != job.expected_h {
-                img = DynamicImage::ImageRgba8(img)
-                    .resize_exact(job.expected_w, job.expected_h, ResizeFilterType::Lanczos3)
-                    .to_rgba8();
-            }
-
-            Ok(LoadedTile {
-                r: job.r,
-                c: job.c,
-                start_x: job.start_x,
-                start_y: job.start_y,
-                image: img,
-            })
-        })
-        .collect::<Result<_, String>>()?;
-
+    let mut loaded_tiles = ctx.load_tiles(jobs)?;
     loaded_tiles.sort_unstable_by_key(|t| (t.r, t.c));
+    let guard_mask = ctx.write_guard_mask(&loaded_tiles)?;
 
     let mut final_img = RgbaImage::new(original_w, original_h);
     let final_stride = original_w as usize * 4;
@@ -535,8 +1015,8 @@ pub fn merge_tiles(
                     if remove_bg {
                         let p_new = Rgba(new_px);
                         let p_old = Rgba(old_px);
-                        let p_new_key = is_key_color(&p_new, key_color, tolerance);
-                        let p_old_key = is_key_color(&p_old, key_color, tolerance);
+                        let p_new_key = ctx.is_key(&p_new);
+                        let p_old_key = ctx.is_key(&p_old);
 
                         if p_new_key && !p_old_key {
                             continue;
@@ -569,40 +1049,193 @@ pub fn merge_tiles(
                         continue;
                     }
 
-                    let inv = 1.0 - factor;
-                    final_raw[dst_idx] = (inv * old_px[0] as f32 + factor * new_px[0] as f32) as u8;
-                    final_raw[dst_idx + 1] =
-                        (inv * old_px[1] as f32 + factor * new_px[1] as f32) as u8;
-                    final_raw[dst_idx + 2] =
-                        (inv * old_px[2] as f32 + factor * new_px[2] as f32) as u8;
-                    final_raw[dst_idx + 3] =
-                        (inv * old_px[3] as f32 + factor * new_px[3] as f32) as u8;
+                    let blended = lerp_premultiplied_in(
+                        &Rgba(old_px),
+                        &Rgba(new_px),
+                        factor,
+                        options.color_space,
+                    );
+                    final_raw[dst_idx..dst_idx + 4].copy_from_slice(&blended.0);
                 }
             }
         }
     }
 
-    if remove_bg {
-        final_img
-            .as_flat_samples_mut()
-            .as_mut_slice()
-            .par_chunks_exact_mut(4)
-            .for_each(|pixel| {
-                let p = Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]);
-                if is_key_color(&p, key_color, tolerance) {
-                    pixel[0] = 0;
-                    pixel[1] = 0;
-                    pixel[2] = 0;
-                    pixel[3] = 0;
+    ctx.finish(final_img, guard_mask.as_ref())
+}
+
+/// Depth of the overlap each side of `rect` shares with the other tiles, capped at half
+/// the tile size. Sides on the image border or without neighbours get no ramp.
+fn overlap_ramps(rect: (u32, u32, u32, u32), others: &[(u32, u32, u32, u32)]) -> [u32; 4] {
+    let (x, y, w, h) = rect;
+    let (x2, y2) = (x + w, y + h);
+    let mut ramps = [0u32; 4]; // left, right, top, bottom
+    for &(ox, oy, ow, oh) in others {
+        let (ox2, oy2) = (ox + ow, oy + oh);
+        if (ox, oy, ow, oh) == rect || ox >= x2 || ox2 <= x || oy >= y2 || oy2 <= y {
+            continue;
+        }
+        if ox < x {
+            ramps[0] = ramps[0].max(ox2.min(x2) - x);
+        }
+        if ox2 > x2 {
+            ramps[1] = ramps[1].max(x2 - ox.max(x));
+        }
+        if oy < y {
+            ramps[2] = ramps[2].max(oy2.min(y2) - y);
+        }
+        if oy2 > y2 {
+            ramps[3] = ramps[3].max(y2 - oy.max(y));
+        }
+    }
+    ramps[0] = ramps[0].min(w / 2);
+    ramps[1] = ramps[1].min(w / 2);
+    ramps[2] = ramps[2].min(h / 2);
+    ramps[3] = ramps[3].min(h / 2);
+    ramps
+}
+
+/// Merges tiles with arbitrary rectangles, as produced by the adaptive planners.
+///
+/// Tiles are painted largest first; each one fades in over the depth it overlaps its
+/// neighbours on every side, so small detail tiles blend into the large ones around them.
+pub fn merge_tile_rects(
+    tiles: Vec<TileInfo>,
+    original_w: u32,
+    original_h: u32,
+    key_color: &str,
+    remove_bg: bool,
+    tolerance: u8,
+    options: &MergeOptions,
+) -> Result<String, String> {
+    if tiles.is_empty() {
+        return Err("No tiles to merge".to_string());
+    }
+    if original_w == 0 || original_h == 0 {
+        return Err("Invalid original image dimensions".to_string());
+    }
+    // Dual-background tiles already carry their alpha.
+    let remove_bg = remove_bg && !options.dual_background;
+
+    let ctx = MergeContext::new(
+        &tiles[0].path,
+        original_w,
+        original_h,
+        key_color,
+        remove_bg,
+        tolerance,
+        options,
+    )?;
+
+    let jobs: Vec<TileJob> = tiles
+        .into_iter()
+        .filter_map(|tile| {
+            if tile.x >= original_w || tile.y >= original_h {
+                return None;
+            }
+            let expected_w = tile.width.min(original_w - tile.x);
+            let expected_h = tile.height.min(original_h - tile.y);
+            if expected_w == 0 || expected_h == 0 {
+                return None;
+            }
+            let original_path = Some(tile.original_path.trim())
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from);
+
+            Some(TileJob {
+                r: tile.r,
+                c: tile.c,
+                start_x: tile.x,
+                start_y: tile.y,
+                expected_w,
+                expected_h,
+                path: tile.path,
+                original_path,
+            })
+        })
+        .collect();
+
+    if jobs.is_empty() {
+        return Err("No valid tiles to merge".to_string());
+    }
+
+    let mut loaded_tiles = ctx.load_tiles(jobs)?;
+    loaded_tiles.sort_unstable_by_key(|t| {
+        (
+            std::cmp::Reverse(t.image.width() * t.image.height()),
+            t.start_y,
+            t.start_x,
+        )
+    });
+    let guard_mask = ctx.write_guard_mask(&loaded_tiles)?;
+
+    let rects: Vec<(u32, u32, u32, u32)> = loaded_tiles
+        .iter()
+        .map(|t| (t.start_x, t.start_y, t.image.width(), t.image.height()))
+        .collect();
+
+    let mut final_img = RgbaImage::new(original_w, original_h);
+    let mut covered = vec![false; original_w as usize * original_h as usize];
+    for (tile, rect) in loaded_tiles.iter().zip(rects.iter()) {
+        let [left, right, top, bottom] = overlap_ramps(*rect, &rects);
+        let (tile_w, tile_h) = tile.image.dimensions();
+        let ramp = |dist: u32, depth: u32| -> f32 {
+            if depth == 0 {
+                1.0
+            } else {
+                (dist as f32 / depth as f32).min(1.0)
+            }
+        };
+
+        for (x, y, new_px) in tile.image.enumerate_pixels() {
+            let (gx, gy) = (tile.start_x + x, tile.start_y + y);
+            let idx = gy as usize * original_w as usize + gx as usize;
+            if !covered[idx] {
+                covered[idx] = true;
+                final_img.put_pixel(gx, gy, *new_px);
+                continue;
+            }
+
+            let old_px = *final_img.get_pixel(gx, gy);
+            if remove_bg {
+                let new_key = ctx.is_key(new_px);
+                let old_key = ctx.is_key(&old_px);
+                if new_key && !old_key {
+                    continue;
+                }
+                if !new_key && old_key {
+                    final_img.put_pixel(gx, gy, *new_px);
+                    continue;
                 }
-            });
+            }
+
+            let factor = ramp(x, left)
+                .min(ramp(tile_w - 1 - x, right))
+                .min(ramp(y, top))
+                .min(ramp(tile_h - 1 - y, bottom));
+            if factor <= 0.0 {
+                continue;
+            }
+            let blended = lerp_premultiplied_in(&old_px, new_px, factor, options.color_space);
+            final_img.put_pixel(gx, gy, blended);
+        }
     }
 
-    if remove_bg {
-        encode_png_data_url_fast(&final_img)
+    let fill = options.uncovered_fill.unwrap_or(if remove_bg {
+        UncoveredFill::Transparent
     } else {
-        encode_jpeg_data_url_fast(&final_img, 90)
+        UncoveredFill::KeyColor
+    });
+    if fill == UncoveredFill::KeyColor && covered.iter().any(|c| !c) {
+        let [r, g, b] = ctx.key.fill_rgb();
+        for (px, is_covered) in final_img.pixels_mut().zip(covered.iter()) {
+            if !is_covered {
+                *px = Rgba([r, g, b, 255]);
+            }
+        }
     }
+
+    ctx.finish(final_img, guard_mask.as_ref())
 }
 
 #[cfg(test)]
@@ -611,8 +1244,27 @@ mod tests {
 
     #[test]
     fn test_is_key_color() {
-        assert!(is_key_color(&Rgba([255, 255, 255, 255]), "white", 10));
-        assert!(is_key_color(&Rgba([0, 0, 0, 0]), "white", 10));
-        assert!(!is_key_color(&Rgba([255, 0, 0, 255]), "white", 10));
+        let white = KeyMatcher::parse("white", 10).unwrap();
+        assert!(white.is_key(&Rgba([255, 255, 255, 255])));
+        assert!(white.is_key(&Rgba([0, 0, 0, 0])));
+        assert!(!white.is_key(&Rgba([255, 0, 0, 255])));
+    }
+
+    #[test]
+    fn test_brick_layout_offsets_alternate_rows() {
+        let grid = GridGeometry::new(400, 300, 3, 4, 0.2, 0.2)
+            .unwrap()
+            .with_layout(TileLayout::Brick);
+
+        assert_eq!(grid.row_len(0), 4);
+        assert_eq!(grid.row_len(1), 5);
+        let (first_x, _, first_w, _) = grid.tile_rect(1, 0).unwrap();
+        let (second_x, _, _, _) = grid.tile_rect(1, 1).unwrap();
+        assert_eq!(first_x, 0);
+        assert_eq!(second_x, grid.stride_w - grid.stride_w / 2);
+        assert!(first_w - second_x >= grid.overlap_w);
+
+        let (last_x, _, last_w, _) = grid.tile_rect(1, 4).unwrap();
+        assert_eq!(last_x + last_w, 400);
     }
 }
@@ -0,0 +1,482 @@
+use crate::filters::ColorSpace;
+use image::Rgba;
+
+/// How pixel colours are compared with the key colours.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyDistance {
+    /// Euclidean distance in RGB.
+    #[default]
+    Rgb,
+    /// CIELAB ΔE2000, close to perceived difference.
+    DeltaE2000,
+    /// Hue angle and chroma, ignoring lightness so shaded chroma screens still key.
+    HueChroma,
+}
+
+/// Soft keyer thresholds, as RGB Euclidean distances (0-441) to the nearest key colour.
+/// Lab and hue specs are measured in their own metric, scaled so the hard-key
+/// threshold falls at the same distance as with RGB.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SoftKeyOptions {
+    /// At or below this distance a pixel is fully background.
+    pub inner: f32,
+    /// At or above this distance a pixel is fully foreground.
+    pub outer: f32,
+    /// Unmix the key colour out of semi-transparent pixels.
+    pub despill: bool,
+}
+
+impl Default for SoftKeyOptions {
+    fn default() -> Self {
+        Self {
+            inner: 40.0,
+            outer: 120.0,
+            despill: true,
+        }
+    }
+}
+
+/// Below this CIELAB chroma a key colour has no meaningful hue.
+const ACHROMATIC_CHROMA: f32 = 10.0;
+
+enum KeyTarget {
+    /// Bare colour name, keyed with the original per-channel thresholds.
+    Named(&'static str),
+    Color {
+        rgb: [u8; 3],
+        lab: [f32; 3],
+    },
+}
+
+/// Key colour spec parsed once into a per-pixel predicate.
+///
+/// Spec syntax is `[metric:]colour[,colour...]`. A metric is `rgb`, `lab` (ΔE2000) or
+/// `hue`; a colour is a name (`white`, `black`, `red`, `green`, `blue`) or `#RRGGBB`.
+pub struct KeyMatcher {
+    targets: Vec<KeyTarget>,
+    distance: KeyDistance,
+    tolerance: u8,
+}
+
+fn named_rgb(name: &str) -> Option<(&'static str, [u8; 3])> {
+    match name {
+        "white" => Some(("white", [255, 255, 255])),
+        "black" => Some(("black", [0, 0, 0])),
+        "red" => Some(("red", [255, 0, 0])),
+        "green" => Some(("green", [0, 255, 0])),
+        "blue" => Some(("blue", [0, 0, 255])),
+        _ => None,
+    }
+}
+
+fn parse_hex(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let expanded: String = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_string(),
+        _ => return None,
+    };
+    let channel = |i: usize| u8::from_str_radix(&expanded[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl KeyMatcher {
+    pub fn parse(spec: &str, tolerance: u8) -> Result<Self, String> {
+        let spec = spec.trim().to_ascii_lowercase();
+        let (distance, colours, explicit) = match spec.split_once(':') {
+            Some((metric, colours)) => {
+                let distance = match metric.trim() {
+                    "rgb" => KeyDistance::Rgb,
+                    "lab" | "de2000" => KeyDistance::DeltaE2000,
+                    "hue" => KeyDistance::HueChroma,
+                    other => return Err(format!("Unknown key colour metric: {}", other)),
+                };
+                (distance, colours, true)
+            }
+            None => (KeyDistance::Rgb, spec.as_str(), false),
+        };
+
+        let mut targets = Vec::new();
+        for colour in colours.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            let target = match named_rgb(colour) {
+                Some((name, _)) if !explicit => KeyTarget::Named(name),
+                Some((_, rgb)) => KeyTarget::Color {
+                    rgb,
+                    lab: rgb_to_lab(rgb),
+                },
+                None => {
+                    let rgb = parse_hex(colour)
+                        .ok_or_else(|| format!("Invalid key colour: {}", colour))?;
+                    KeyTarget::Color {
+                        rgb,
+                        lab: rgb_to_lab(rgb),
+                    }
+                }
+            };
+            targets.push(target);
+        }
+        if targets.is_empty() {
+            targets.push(KeyTarget::Named("white"));
+        }
+
+        Ok(Self {
+            targets,
+            distance,
+            tolerance,
+        })
+    }
+
+    /// RGB of the first key colour, used to fill uncovered pixels.
+    pub fn fill_rgb(&self) -> [u8; 3] {
+        Self::target_rgb(&self.targets[0])
+    }
+
+    fn target_rgb(target: &KeyTarget) -> [u8; 3] {
+        match target {
+            KeyTarget::Named(name) => named_rgb(name).map(|(_, rgb)| rgb).unwrap_or([255; 3]),
+            KeyTarget::Color { rgb, .. } => *rgb,
+        }
+    }
+
+    /// Keys `p` softly: alpha follows the distance to the nearest key colour, and
+    /// semi-transparent pixels have that colour unmixed from them when despilling.
+    pub fn soft_key(&self, p: &Rgba<u8>, options: &SoftKeyOptions) -> Rgba<u8> {
+        let rgb = [p[0], p[1], p[2]];
+        let mut lab = None;
+        let (distance, key) = self
+            .targets
+            .iter()
+            .map(|target| {
+                let key = Self::target_rgb(target);
+                let distance = match target {
+                    KeyTarget::Named(_) => rgb_distance(rgb, key),
+                    KeyTarget::Color { lab: key_lab, .. } => {
+                        self.color_distance(rgb, &mut lab, key, || *key_lab)
+                    }
+                };
+                (distance, key)
+            })
+            .fold((f32::MAX, [0; 3]), |best, next| {
+                if next.0 < best.0 {
+                    next
+                } else {
+                    best
+                }
+            });
+        soft_key_against(p, distance, key, options)
+    }
+
+    /// Like [`Self::soft_key`], against a local background estimate instead of the
+    /// key colours.
+    pub fn soft_key_near(
+        &self,
+        p: &Rgba<u8>,
+        background: [u8; 3],
+        options: &SoftKeyOptions,
+    ) -> Rgba<u8> {
+        let distance = self.color_distance([p[0], p[1], p[2]], &mut None, background, || {
+            rgb_to_lab(background)
+        });
+        soft_key_against(p, distance, background, options)
+    }
+
+    pub fn is_key(&self, p: &Rgba<u8>) -> bool {
+        if p[3] < 10 {
+            return true;
+        }
+        let rgb = [p[0], p[1], p[2]];
+        // Lab is only computed when a target needs it, and at most once per pixel.
+        let mut lab = None;
+        self.targets.iter().any(|target| match target {
+            KeyTarget::Named(name) => named_matches(name, rgb, self.tolerance),
+            KeyTarget::Color {
+                rgb: key,
+                lab: key_lab,
+            } => self.color_matches(rgb, &mut lab, *key, || *key_lab),
+        })
+    }
+
+    /// Whether `p` matches a local background estimate, with the spec's metric and
+    /// tolerance. Named keys are compared in RGB.
+    pub fn is_key_near(&self, p: &Rgba<u8>, background: [u8; 3]) -> bool {
+        if p[3] < 10 {
+            return true;
+        }
+        // The background's Lab is only needed, and computed, for Lab and hue specs.
+        self.color_matches([p[0], p[1], p[2]], &mut None, background, || {
+            rgb_to_lab(background)
+        })
+    }
+
+    fn color_matches(
+        &self,
+        rgb: [u8; 3],
+        lab: &mut Option<[f32; 3]>,
+        key: [u8; 3],
+        key_lab: impl FnOnce() -> [f32; 3],
+    ) -> bool {
+        self.color_distance(rgb, lab, key, key_lab) <= self.rgb_threshold()
+    }
+
+    /// Hard-key threshold for RGB distance at the spec's tolerance.
+    fn rgb_threshold(&self) -> f32 {
+        24.0 + 2.0 * self.tolerance as f32
+    }
+
+    /// Distance in the spec's metric, scaled so its hard-key threshold lands on
+    /// [`Self::rgb_threshold`].
+    fn color_distance(
+        &self,
+        rgb: [u8; 3],
+        lab: &mut Option<[f32; 3]>,
+        key: [u8; 3],
+        key_lab: impl FnOnce() -> [f32; 3],
+    ) -> f32 {
+        let tolerance = self.tolerance as f32;
+        match self.distance {
+            KeyDistance::Rgb => rgb_distance(rgb, key),
+            KeyDistance::DeltaE2000 => {
+                let lab = *lab.get_or_insert_with(|| rgb_to_lab(rgb));
+                delta_e2000(lab, key_lab()) / delta_e_threshold(tolerance) * self.rgb_threshold()
+            }
+            KeyDistance::HueChroma => {
+                let lab = *lab.get_or_insert_with(|| rgb_to_lab(rgb));
+                hue_chroma_distance(lab, key_lab(), tolerance) * self.rgb_threshold()
+            }
+        }
+    }
+}
+
+/// Soft keying of `p` at `distance` (in RGB units) from the background colour `key`.
+fn soft_key_against(
+    p: &Rgba<u8>,
+    distance: f32,
+    key: [u8; 3],
+    options: &SoftKeyOptions,
+) -> Rgba<u8> {
+    let span = (options.outer - options.inner).max(1e-3);
+    let key_alpha = ((distance - options.inner) / span).clamp(0.0, 1.0);
+    if key_alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let alpha = (p[3] as f32 * key_alpha).round() as u8;
+    if alpha == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    if key_alpha >= 1.0 || !options.despill {
+        return Rgba([p[0], p[1], p[2], alpha]);
+    }
+
+    // Observed = a * foreground + (1 - a) * key, solved for the foreground.
+    let unmix = |ch: usize| {
+        let k = key[ch] as f32;
+        (k + (p[ch] as f32 - k) / key_alpha)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    Rgba([unmix(0), unmix(1), unmix(2), alpha])
+}
+
+fn named_matches(name: &str, p: [u8; 3], tolerance: u8) -> bool {
+    let white_min = 240u8.saturating_sub(tolerance);
+    let black_max = 15u8.saturating_add(tolerance);
+    let color_min = 240u8.saturating_sub(tolerance);
+    let color_max = 50u8.saturating_add(tolerance);
+
+    match name {
+        "black" => p[0] <= black_max && p[1] <= black_max && p[2] <= black_max,
+        "red" => p[0] >= color_min && p[1] <= color_max && p[2] <= color_max,
+        "blue" => p[0] <= color_max && p[1] <= color_max && p[2] >= color_min,
+        "green" => p[0] <= color_max && p[1] >= color_min && p[2] <= color_max,
+        _ => p[0] >= white_min && p[1] >= white_min && p[2] >= white_min,
+    }
+}
+
+fn rgb_distance(a: [u8; 3], b: [u8; 3]) -> f32 {
+    (0..3)
+        .map(|ch| (a[ch] as f32 - b[ch] as f32).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+fn delta_e_threshold(tolerance: f32) -> f32 {
+    3.0 + 0.5 * tolerance
+}
+
+/// Hue-angle distance as a fraction of the hue threshold; infinite when the pixel
+/// has too little chroma to share the key's hue.
+fn hue_chroma_distance(lab: [f32; 3], key: [f32; 3], tolerance: f32) -> f32 {
+    let key_chroma = key[1].hypot(key[2]);
+    if key_chroma < ACHROMATIC_CHROMA {
+        return delta_e2000(lab, key) / delta_e_threshold(tolerance);
+    }
+    let chroma = lab[1].hypot(lab[2]);
+    if chroma < key_chroma * (0.4 - tolerance / 400.0).max(0.1) {
+        return f32::INFINITY;
+    }
+    let hue = lab[2].atan2(lab[1]).to_degrees();
+    let key_hue = key[2].atan2(key[1]).to_degrees();
+    let diff = (hue - key_hue).rem_euclid(360.0);
+    diff.min(360.0 - diff) / (12.0 + 0.6 * tolerance)
+}
+
+/// sRGB to CIELAB (D65).
+pub fn rgb_to_lab(rgb: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|v| ColorSpace::Linear.decode(v));
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIEDE2000 colour difference between two CIELAB colours.
+pub fn delta_e2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let pow25_7 = 25.0f32.powi(7);
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+    let (a1p, a2p) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1p, c2p) = (a1p.hypot(b1), a2p.hypot(b2));
+    let hue = |b: f32, a: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1p, h2p) = (hue(b1, a1p), hue(b2, a2p));
+
+    let dl = l2 - l1;
+    let dc = c2p - c1p;
+    let chroma_product = c1p * c2p;
+    let dh_angle = if chroma_product == 0.0 {
+        0.0
+    } else {
+        let d = h2p - h1p;
+        if d > 180.0 {
+            d - 360.0
+        } else if d < -180.0 {
+            d + 360.0
+        } else {
+            d
+        }
+    };
+    let dh = 2.0 * chroma_product.sqrt() * (dh_angle / 2.0).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar = if chroma_product == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let cos_deg = |deg: f32| deg.to_radians().cos();
+    let t = 1.0 - 0.17 * cos_deg(h_bar - 30.0)
+        + 0.24 * cos_deg(2.0 * h_bar)
+        + 0.32 * cos_deg(3.0 * h_bar + 6.0)
+        - 0.20 * cos_deg(4.0 * h_bar - 63.0);
+    let d_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + pow25_7)).sqrt();
+    let l_dev = (l_bar - 50.0).powi(2);
+    let sl = 1.0 + 0.015 * l_dev / (20.0 + l_dev).sqrt();
+    let sc = 1.0 + 0.045 * c_bar_p;
+    let sh = 1.0 + 0.015 * c_bar_p * t;
+    let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+    let (tl, tc, th) = (dl / sl, dc / sc, dh / sh);
+    (tl * tl + tc * tc + th * th + rt * tc * th).max(0.0).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_e2000_reference_pairs() {
+        // Pairs from Sharma, Wu and Dalal's CIEDE2000 test data.
+        let cases = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            (
+                [2.0776, 0.0795, -1.1350],
+                [0.9033, -0.0636, -0.5514],
+                0.9082,
+            ),
+        ];
+        for (a, b, expected) in cases {
+            assert!((delta_e2000(a, b) - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_key_matcher_specs() {
+        let named = KeyMatcher::parse("white", 10).unwrap();
+        assert!(named.is_key(&Rgba([235, 235, 235, 255])));
+        assert!(!named.is_key(&Rgba([255, 0, 0, 255])));
+
+        let multi = KeyMatcher::parse("#FF00FF, #00b140", 10).unwrap();
+        assert!(multi.is_key(&Rgba([250, 10, 245, 255])));
+        assert!(multi.is_key(&Rgba([0, 177, 64, 255])));
+        assert!(!multi.is_key(&Rgba([128, 128, 128, 255])));
+        assert_eq!(multi.fill_rgb(), [255, 0, 255]);
+
+        // A shadowed green screen keeps its hue even though it is much darker.
+        let hue = KeyMatcher::parse("hue:#00ff00", 10).unwrap();
+        assert!(hue.is_key(&Rgba([20, 110, 25, 255])));
+        assert!(!hue.is_key(&Rgba([110, 110, 20, 255])));
+        assert!(!KeyMatcher::parse("#00ff00", 10)
+            .unwrap()
+            .is_key(&Rgba([20, 110, 25, 255])));
+
+        assert!(KeyMatcher::parse("lab:#12345", 10).is_err());
+    }
+
+    #[test]
+    fn test_key_matcher_rejects_non_ascii_hex() {
+        assert!(KeyMatcher::parse("#aéaaa", 10).is_err());
+        assert!(KeyMatcher::parse("lab:#é0", 10).is_err());
+    }
+
+    #[test]
+    fn test_soft_key_ramps_alpha_and_unmixes_key() {
+        let green = KeyMatcher::parse("green", 10).unwrap();
+        let options = SoftKeyOptions::default();
+        assert_eq!(green.soft_key(&Rgba([5, 250, 5, 255]), &options)[3], 0);
+        assert_eq!(
+            green.soft_key(&Rgba([200, 40, 60, 255]), &options),
+            Rgba([200, 40, 60, 255])
+        );
+
+        // A thin red strand over the green screen, covering 30% of the pixel.
+        let edge = green.soft_key(&Rgba([60, 190, 18, 255]), &options);
+        assert!(edge[3] > 0 && edge[3] < 255);
+        assert!(edge[0] > 60 && edge[1] < 190);
+
+        // Hue specs soft key by hue, like their hard key: shaded green is background.
+        let hue = KeyMatcher::parse("hue:#00ff00", 10).unwrap();
+        let shaded = Rgba([20, 110, 25, 255]);
+        assert_eq!(hue.soft_key(&shaded, &options)[3], 0);
+        assert_eq!(hue.soft_key(&Rgba([200, 40, 60, 255]), &options)[3], 255);
+        assert_eq!(hue.soft_key_near(&shaded, [0, 255, 0], &options)[3], 0);
+        assert!(green.soft_key(&shaded, &options)[3] > 0);
+    }
+}
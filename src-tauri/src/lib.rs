@@ -1,8 +1,10 @@
 use base64::{engine::general_purpose, Engine as _};
+use checkerboard::CheckerboardReport;
+use compositor::{BlendMode, PlacedOverlay};
 use image::imageops::{crop_imm, FilterType as ResizeFilterType};
 use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
-use image::{ColorType, DynamicImage, ImageEncoder, RgbaImage};
+use image::{ColorType, DynamicImage, ImageEncoder, Rgba, RgbaImage};
 use psd_rs::{Document, Layer};
 use std::fs;
 use std::io::BufWriter;
@@ -10,8 +12,40 @@ use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use tempfile::TempDir;
 
+mod background_model;
+mod checkerboard;
+mod compositor;
+mod dual_background;
+mod fidelity;
+mod filters;
+mod flood_fill;
 mod image_processing;
-use image_processing::{merge_tiles, split_image, TileInfo};
+mod keying;
+mod matting;
+mod morphology;
+mod outpaint;
+mod psd_layers;
+mod region_blend;
+mod region_mask;
+mod seamless;
+mod seams;
+mod shadow;
+mod tiling;
+use filters::{resize_rgba, resize_rgba_in, ColorSpace};
+use image_processing::{
+    decode_data_url, merge_tile_rects, merge_tiles, split_image, MergeOptions, TileInfo,
+    TileLayout, SUBJECT_GUARD_MASK_FILE,
+};
+use morphology::{clean_alpha, AlphaCleanupOptions};
+use outpaint::{CanvasExtension, OutpaintTile, OUTPAINT_MASK_FILE};
+use psd_layers::PsdLayerProps;
+use region_blend::{RegionBlendOptions, RegionShape};
+use region_mask::{RegionMask, REGION_MASK_FILE};
+use seamless::EdgeMismatch;
+use shadow::SHADOW_LAYER_FILE;
+use tiling::{
+    GridPlan, ModelCapabilities, PlanPreference, QuadtreeOptions, RoiOptions, SubjectRoi,
+};
 
 // State to hold temp directory
 struct AppState {
@@ -27,12 +61,35 @@ struct SplitResponse {
     new_input_path: String,
 }
 
+#[derive(serde::Serialize)]
+struct OutpaintSplitResponse {
+    tiles: Vec<OutpaintTile>,
+    original_width: u32,
+    original_height: u32,
+    temp_dir: String,
+    new_input_path: String,
+    mask_path: String,
+}
+
+#[derive(serde::Serialize)]
+struct RoiSplitResponse {
+    tiles: Vec<TileInfo>,
+    original_width: u32,
+    original_height: u32,
+    temp_dir: String,
+    new_input_path: String,
+    roi: SubjectRoi,
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 struct PreparedTileResponse {
     input_data_url: String,
     output_path: String,
     original_path: String,
+    /// Outputs of the on-white and on-black generations for dual-background alpha.
+    white_path: String,
+    black_path: String,
 }
 
 #[tauri::command]
@@ -76,6 +133,32 @@ fn save_image_resized(
     image_processing::save_resized_tile(&path, &data, width, height)
 }
 
+/// Solves alpha from a tile's on-white and on-black generations and writes the
+/// RGBA result to `path`, which should be a PNG.
+#[tauri::command]
+async fn solve_dual_background_tile(path: String, width: u32, height: u32) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        dual_background::solve_dual_tile_to_output(&path, width, height)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Checks a generated tile for a painted transparency checkerboard and, with
+/// `remove`, clears it (PNG) or paints it with the key colour (JPEG).
+#[tauri::command]
+async fn check_tile_checkerboard(
+    path: String,
+    remove: bool,
+    key_color: Option<String>,
+) -> Result<CheckerboardReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        checkerboard::check_tile_checkerboard(&path, remove, key_color.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 fn load_image_region(
     path: String,
@@ -107,9 +190,7 @@ fn prepare_tile_from_data_url(
         .map_err(|e| format!("Failed to decode tile image: {}", e))?
         .to_rgba8();
     if tile_image.width() != width || tile_image.height() != height {
-        tile_image = DynamicImage::ImageRgba8(tile_image)
-            .resize_exact(width, height, ResizeFilterType::Lanczos3)
-            .to_rgba8();
+        tile_image = resize_rgba(&tile_image, width, height, ResizeFilterType::Lanczos3);
     }
 
     let mut state_temp = state
@@ -142,11 +223,14 @@ fn prepare_tile_from_data_url(
     let original_path_str = original_path.to_string_lossy().to_string();
     let output_path_str = output_path.to_string_lossy().to_string();
     let input_data_url = load_image(original_path_str.clone())?;
+    let (white_path, black_path) = dual_background::dual_background_paths(&output_path_str);
 
     Ok(PreparedTileResponse {
         input_data_url,
         output_path: output_path_str,
         original_path: original_path_str,
+        white_path: white_path.to_string_lossy().to_string(),
+        black_path: black_path.to_string_lossy().to_string(),
     })
 }
 
@@ -178,11 +262,15 @@ fn prepare_tile_paths(
     let ext = image_format.ext();
     let original_path = td_path.join(format!("orig_tile_{}_{}.{}", row, col, ext));
     let output_path = td_path.join(format!("tile_{}_{}.{}", row, col, ext));
+    let (white_path, black_path) =
+        dual_background::dual_background_paths(&output_path.to_string_lossy());
 
     Ok(PreparedTileResponse {
         input_data_url: String::new(),
         output_path: output_path.to_string_lossy().to_string(),
         original_path: original_path.to_string_lossy().to_string(),
+        white_path: white_path.to_string_lossy().to_string(),
+        black_path: black_path.to_string_lossy().to_string(),
     })
 }
 
@@ -199,6 +287,7 @@ fn save_image_region_blend(
     region_width: u32,
     region_height: u32,
     fallback_path: Option<String>,
+    options: Option<RegionBlendOptions>,
 ) -> Result<(), String> {
     if tile_width == 0 || tile_height == 0 || region_width == 0 || region_height == 0 {
         return Ok(());
@@ -210,57 +299,219 @@ fn save_image_region_blend(
         .map_err(|e| e.to_string())?;
     let generated = image::load_from_memory(&data)
         .map_err(|e| e.to_string())?
-        .resize_exact(region_width, region_height, ResizeFilterType::Lanczos3)
         .to_rgba8();
+    let generated = resize_rgba(
+        &generated,
+        region_width,
+        region_height,
+        ResizeFilterType::Lanczos3,
+    );
 
-    let mut base = if Path::new(&path).is_file() {
-        image::open(&path)
-            .map_err(|e| e.to_string())?
-            .resize_exact(tile_width, tile_height, ResizeFilterType::Lanczos3)
-            .to_rgba8()
-    } else if let Some(fallback) = fallback_path
-        .map(|v| v.trim().to_string())
-        .filter(|v| !v.is_empty())
-    {
-        if Path::new(&fallback).is_file() {
-            image::open(&fallback)
-                .map_err(|e| e.to_string())?
-                .resize_exact(tile_width, tile_height, ResizeFilterType::Lanczos3)
-                .to_rgba8()
-        } else {
-            RgbaImage::new(tile_width, tile_height)
-        }
-    } else {
-        RgbaImage::new(tile_width, tile_height)
-    };
+    let mut base = region_blend::load_tile_for_blend(
+        &path,
+        fallback_path.as_deref(),
+        tile_width,
+        tile_height,
+    )?;
+    let mask = region_blend::region_mask(
+        &RegionShape::Rect,
+        region_x,
+        region_y,
+        region_width,
+        region_height,
+    )?;
+    region_blend::blend_region_with_options(
+        &mut base,
+        tile_x,
+        tile_y,
+        &generated,
+        mask,
+        region_x,
+        region_y,
+        &options.unwrap_or_default(),
+    );
 
-    let region_x2 = region_x.saturating_add(region_width);
-    let region_y2 = region_y.saturating_add(region_height);
+    image_processing::save_rgba_image_auto(&path, &base)
+}
 
-    let tile_x2 = tile_x.saturating_add(tile_width);
-    let tile_y2 = tile_y.saturating_add(tile_height);
+#[tauri::command]
+async fn render_region_mask(
+    region_x: u32,
+    region_y: u32,
+    region_width: u32,
+    region_height: u32,
+    shape: RegionShape,
+) -> Result<String, String> {
+    if region_width == 0 || region_height == 0 {
+        return Err("Region width/height must be greater than 0".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let mask =
+            region_blend::region_mask(&shape, region_x, region_y, region_width, region_height)?;
+        let rgba = DynamicImage::ImageLuma8(mask).to_rgba8();
+        image_processing::encode_png_data_url_fast(&rgba)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    let overlap_x1 = tile_x.max(region_x);
-    let overlap_y1 = tile_y.max(region_y);
-    let overlap_x2 = tile_x2.min(region_x2);
-    let overlap_y2 = tile_y2.min(region_y2);
+#[tauri::command]
+async fn save_image_mask_blend(
+    base64_data: String,
+    region_x: u32,
+    region_y: u32,
+    region_width: u32,
+    region_height: u32,
+    shape: RegionShape,
+    options: Option<RegionBlendOptions>,
+    tiles: Vec<TileInfo>,
+) -> Result<Vec<TileKey>, String> {
+    if region_width == 0 || region_height == 0 {
+        return Ok(Vec::new());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let raw = decode_data_url(&base64_data)?;
+        let generated = image::load_from_memory(&raw)
+            .map_err(|e| e.to_string())?
+            .to_rgba8();
+        let generated = resize_rgba(
+            &generated,
+            region_width,
+            region_height,
+            ResizeFilterType::Lanczos3,
+        );
+        let mask =
+            region_blend::region_mask(&shape, region_x, region_y, region_width, region_height)?;
+
+        let updated = region_blend::blend_region_into_tiles(
+            &tiles,
+            &generated,
+            mask,
+            region_x,
+            region_y,
+            &options.unwrap_or_default(),
+        )?;
+        Ok(updated.into_iter().map(|(r, c)| TileKey { r, c }).collect())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn set_region_mask(
+    state: tauri::State<'_, AppState>,
+    base64_data: String,
+    width: u32,
+    height: u32,
+) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("Mask width/height must be greater than 0".to_string());
+    }
+
+    let raw = decode_data_url(&base64_data)?;
+    let mut mask_image = image::load_from_memory(&raw)
+        .map_err(|e| format!("Failed to decode region mask: {}", e))?
+        .to_rgba8();
+    if mask_image.width() != width || mask_image.height() != height {
+        mask_image = DynamicImage::ImageRgba8(mask_image)
+            .resize_exact(width, height, ResizeFilterType::Nearest)
+            .to_rgba8();
+    }
 
-    if overlap_x1 >= overlap_x2 || overlap_y1 >= overlap_y2 {
-        return image_processing::save_rgba_image_auto(&path, &base);
+    let mut state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    if state_temp.is_none() {
+        *state_temp = Some(TempDir::new().map_err(|e| e.to_string())?);
     }
+    let td_path = state_temp
+        .as_ref()
+        .ok_or_else(|| "Temp directory is unavailable".to_string())?
+        .path()
+        .to_path_buf();
 
-    for gy in overlap_y1..overlap_y2 {
-        let local_y = gy - tile_y;
-        for gx in overlap_x1..overlap_x2 {
-            let local_x = gx - tile_x;
-            let region_local_x = gx - region_x;
-            let region_local_y = gy - region_y;
-            let px = generated.get_pixel(region_local_x, region_local_y);
-            base.put_pixel(local_x, local_y, *px);
+    let mask = RegionMask::from_rgba(&mask_image);
+    save_png_fast(&td_path.join(REGION_MASK_FILE), &mask.to_rgba())
+}
+
+#[tauri::command]
+fn clear_region_mask(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    if let Some(td) = state_temp.as_ref() {
+        let mask_path = td.path().join(REGION_MASK_FILE);
+        if mask_path.is_file() {
+            fs::remove_file(mask_path).map_err(|e| e.to_string())?;
         }
     }
+    Ok(())
+}
 
-    image_processing::save_rgba_image_auto(&path, &base)
+#[tauri::command]
+fn protected_tile_keys(
+    state: tauri::State<'_, AppState>,
+    tiles: Vec<TileRect>,
+) -> Result<Vec<TileKey>, String> {
+    let state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    let Some(td) = state_temp.as_ref() else {
+        return Ok(Vec::new());
+    };
+    let mask_path = td.path().join(REGION_MASK_FILE);
+    if !mask_path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let mask_image = image::open(&mask_path).map_err(|e| e.to_string())?.to_rgba8();
+    let mask = RegionMask::from_rgba(&mask_image);
+    Ok(tiles
+        .into_iter()
+        .filter(|tile| mask.fully_protected(tile.x, tile.y, tile.width, tile.height))
+        .map(|tile| TileKey {
+            r: tile.r,
+            c: tile.c,
+        })
+        .collect())
+}
+
+/// Unknown-area mask for a tile of an outpainted canvas, as a PNG data URL. `None`
+/// when the session has no outpaint mask for an image of this size or the tile is
+/// fully known.
+#[tauri::command]
+fn outpaint_tile_mask(
+    state: tauri::State<'_, AppState>,
+    image_width: u32,
+    image_height: u32,
+    tile: TileRect,
+) -> Result<Option<String>, String> {
+    let state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    let Some(td) = state_temp.as_ref() else {
+        return Ok(None);
+    };
+    let mask_path = td.path().join(OUTPAINT_MASK_FILE);
+    if !mask_path.is_file() {
+        return Ok(None);
+    }
+
+    let mask = image::open(&mask_path).map_err(|e| e.to_string())?.to_luma8();
+    if mask.dimensions() != (image_width, image_height) {
+        return Ok(None);
+    }
+    let Some((tile_mask, _)) =
+        outpaint::tile_unknown_mask(&mask, tile.x, tile.y, tile.width, tile.height)
+    else {
+        return Ok(None);
+    };
+    let rgba = DynamicImage::ImageLuma8(tile_mask).to_rgba8();
+    image_processing::encode_png_data_url_fast(&rgba).map(Some)
 }
 
 #[tauri::command]
@@ -272,6 +523,8 @@ async fn split_img(
     overlap_ratio_x: f64,
     overlap_ratio_y: f64,
     prefer_jpeg: bool,
+    layout: Option<TileLayout>,
+    plan: Option<GridPlan>,
 ) -> Result<SplitResponse, String> {
     let path_clone = path.clone();
 
@@ -280,15 +533,22 @@ async fn split_img(
         let td = TempDir::new().map_err(|e| e.to_string())?;
         let td_path = td.path().to_path_buf();
 
-        let (tiles, w, h, new_path) = split_image(
-            &path_clone,
-            rows,
-            cols,
-            overlap_ratio_x,
-            overlap_ratio_y,
-            prefer_jpeg,
-            &td_path,
-        )?;
+        // A plan from `plan_grid` carries its own tile rectangles.
+        let (tiles, w, h, new_path) = match plan {
+            Some(plan) => {
+                tiling::split_image_with_plan(&path_clone, &plan, prefer_jpeg, &td_path)?
+            }
+            None => split_image(
+                &path_clone,
+                rows,
+                cols,
+                overlap_ratio_x,
+                overlap_ratio_y,
+                layout.unwrap_or_default(),
+                prefer_jpeg,
+                &td_path,
+            )?,
+        };
 
         Ok::<_, String>((td, tiles, w, h, td_path, new_path))
     })
@@ -312,6 +572,234 @@ async fn split_img(
     })
 }
 
+#[tauri::command]
+fn plan_grid(
+    width: u32,
+    height: u32,
+    model: ModelCapabilities,
+    overlap_ratio: f64,
+    preference: Option<PlanPreference>,
+) -> Result<GridPlan, String> {
+    tiling::plan_grid(
+        width,
+        height,
+        &model,
+        overlap_ratio,
+        preference.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+async fn split_img_wrap(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    prefer_jpeg: bool,
+) -> Result<SplitResponse, String> {
+    let (td, tiles, w, h, td_path_buf, new_path) =
+        tauri::async_runtime::spawn_blocking(move || {
+            let td = TempDir::new().map_err(|e| e.to_string())?;
+            let td_path = td.path().to_path_buf();
+
+            let (tiles, w, h, new_path) = seamless::split_image_wrap(
+                &path,
+                rows,
+                cols,
+                overlap_ratio_x,
+                overlap_ratio_y,
+                prefer_jpeg,
+                &td_path,
+            )?;
+
+            Ok::<_, String>((td, tiles, w, h, td_path, new_path))
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let mut state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    *state_temp = Some(td);
+
+    Ok(SplitResponse {
+        tiles,
+        original_width: w,
+        original_height: h,
+        temp_dir: td_path_buf.to_string_lossy().to_string(),
+        new_input_path: new_path,
+    })
+}
+
+#[tauri::command]
+async fn split_img_outpaint(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    extension: CanvasExtension,
+    prefer_jpeg: bool,
+) -> Result<OutpaintSplitResponse, String> {
+    let (td, tiles, w, h, td_path_buf, new_path) =
+        tauri::async_runtime::spawn_blocking(move || {
+            let td = TempDir::new().map_err(|e| e.to_string())?;
+            let td_path = td.path().to_path_buf();
+
+            let (tiles, w, h, new_path) = outpaint::split_image_outpaint(
+                &path,
+                rows,
+                cols,
+                overlap_ratio_x,
+                overlap_ratio_y,
+                &extension,
+                prefer_jpeg,
+                &td_path,
+            )?;
+
+            Ok::<_, String>((td, tiles, w, h, td_path, new_path))
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let mut state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    *state_temp = Some(td);
+
+    Ok(OutpaintSplitResponse {
+        tiles,
+        original_width: w,
+        original_height: h,
+        mask_path: td_path_buf
+            .join(OUTPAINT_MASK_FILE)
+            .to_string_lossy()
+            .to_string(),
+        temp_dir: td_path_buf.to_string_lossy().to_string(),
+        new_input_path: new_path,
+    })
+}
+
+#[tauri::command]
+async fn split_img_quadtree(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    options: Option<QuadtreeOptions>,
+    prefer_jpeg: bool,
+) -> Result<SplitResponse, String> {
+    let (td, tiles, w, h, td_path_buf, new_path) =
+        tauri::async_runtime::spawn_blocking(move || {
+            let td = TempDir::new().map_err(|e| e.to_string())?;
+            let td_path = td.path().to_path_buf();
+
+            let (tiles, w, h, new_path) = tiling::split_image_quadtree(
+                &path,
+                &options.unwrap_or_default(),
+                prefer_jpeg,
+                &td_path,
+            )?;
+
+            Ok::<_, String>((td, tiles, w, h, td_path, new_path))
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let mut state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    *state_temp = Some(td);
+
+    Ok(SplitResponse {
+        tiles,
+        original_width: w,
+        original_height: h,
+        temp_dir: td_path_buf.to_string_lossy().to_string(),
+        new_input_path: new_path,
+    })
+}
+
+#[tauri::command]
+async fn split_img_roi(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    key_color: String,
+    tolerance: u8,
+    options: Option<RoiOptions>,
+    prefer_jpeg: bool,
+) -> Result<RoiSplitResponse, String> {
+    let (td, tiles, w, h, td_path_buf, new_path, roi) =
+        tauri::async_runtime::spawn_blocking(move || {
+            let options = options.unwrap_or_default();
+            let hint = match options
+                .hint_data_url
+                .as_deref()
+                .filter(|value| !value.trim().is_empty())
+            {
+                Some(data_url) => {
+                    let raw = decode_data_url(data_url)?;
+                    let hint_image = image::load_from_memory(&raw)
+                        .map_err(|e| format!("Failed to decode subject hint: {}", e))?
+                        .to_rgba8();
+                    Some(hint_image)
+                }
+                None => None,
+            };
+
+            let td = TempDir::new().map_err(|e| e.to_string())?;
+            let td_path = td.path().to_path_buf();
+
+            let (tiles, w, h, new_path, roi) = tiling::split_image_roi(
+                &path,
+                rows,
+                cols,
+                overlap_ratio_x,
+                overlap_ratio_y,
+                &key_color,
+                tolerance,
+                &options,
+                hint.as_ref(),
+                prefer_jpeg,
+                &td_path,
+            )?;
+
+            Ok::<_, String>((td, tiles, w, h, td_path, new_path, roi))
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let mut state_temp = state
+        .temp_dir
+        .lock()
+        .map_err(|_| "Failed to lock state".to_string())?;
+    *state_temp = Some(td);
+
+    Ok(RoiSplitResponse {
+        tiles,
+        original_width: w,
+        original_height: h,
+        temp_dir: td_path_buf.to_string_lossy().to_string(),
+        new_input_path: new_path,
+        roi,
+    })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SeamSplitResponse {
+    tiles: Vec<TileInfo>,
+    base_path: String,
+}
+
 #[derive(serde::Deserialize)]
 struct TileUpdate {
     r: u32,
@@ -319,6 +807,22 @@ struct TileUpdate {
     path: String,
 }
 
+#[derive(serde::Deserialize)]
+struct TileRect {
+    r: u32,
+    c: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(serde::Serialize)]
+struct TileKey {
+    r: u32,
+    c: u32,
+}
+
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct SeedTile {
@@ -357,6 +861,33 @@ struct ExportOverlay {
     active_version_index: u64,
     #[serde(default)]
     layer_order: u64,
+    #[serde(default = "default_overlay_visible")]
+    visible: bool,
+    #[serde(default = "default_overlay_opacity")]
+    opacity: f32,
+    #[serde(default)]
+    blend_mode: BlendMode,
+    /// All generated versions; `data_url` is used when empty.
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+fn default_overlay_visible() -> bool {
+    true
+}
+
+fn default_overlay_opacity() -> f32 {
+    1.0
+}
+
+impl ExportOverlay {
+    fn active_data_url(&self) -> &str {
+        self.versions
+            .get(self.active_version_index as usize)
+            .filter(|v| !v.trim().is_empty())
+            .map(String::as_str)
+            .unwrap_or(&self.data_url)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -379,6 +910,7 @@ struct SaveBundleResponse {
     merged_path: String,
     psd_path: String,
     tiles_dir: String,
+    guard_mask_path: String,
     tile_count: usize,
     image_format: String,
     psd_logs: Vec<String>,
@@ -416,11 +948,59 @@ impl ExportImageFormat {
     }
 }
 
-fn decode_data_url(data: &str) -> Result<Vec<u8>, String> {
-    let payload = data.split(',').last().unwrap_or(data);
-    general_purpose::STANDARD
-        .decode(payload)
-        .map_err(|e| e.to_string())
+/// Decodes overlays at their export size, bottom-most first.
+fn decode_overlay_layers(
+    overlays: Vec<ExportOverlay>,
+    color_space: ColorSpace,
+) -> Result<Vec<OverlayLayerExport>, String> {
+    let mut sorted_overlays = overlays;
+    sorted_overlays.sort_unstable_by_key(|o| (o.layer_order, o.id));
+    let mut overlay_layers = Vec::with_capacity(sorted_overlays.len());
+    for overlay in sorted_overlays {
+        if overlay.width == 0
+            || overlay.height == 0
+            || overlay.active_data_url().trim().is_empty()
+        {
+            continue;
+        }
+        let overlay_raw = decode_data_url(overlay.active_data_url())?;
+        let mut overlay_img = image::load_from_memory(&overlay_raw)
+            .map_err(|e| format!("Failed to decode overlay layer {}: {}", overlay.id, e))?
+            .to_rgba8();
+        if overlay_img.width() != overlay.width || overlay_img.height() != overlay.height {
+            overlay_img = resize_rgba_in(
+                &overlay_img,
+                overlay.width,
+                overlay.height,
+                image::imageops::FilterType::Lanczos3,
+                color_space,
+            );
+        }
+        overlay_layers.push(OverlayLayerExport {
+            overlay,
+            image: overlay_img,
+        });
+    }
+    Ok(overlay_layers)
+}
+
+fn flatten_visible_overlays(
+    base: &RgbaImage,
+    overlay_layers: &[OverlayLayerExport],
+    color_space: ColorSpace,
+) -> RgbaImage {
+    let placed: Vec<PlacedOverlay> = overlay_layers
+        .iter()
+        .filter(|layer| layer.overlay.visible)
+        .map(|layer| PlacedOverlay {
+            x: layer.overlay.x,
+            y: layer.overlay.y,
+            image: &layer.image,
+            opacity: layer.overlay.opacity,
+            blend: layer.overlay.blend_mode,
+        })
+        .collect();
+    compositor::flatten_overlays(base, &placed, color_space)
 }
 
 fn flatten_rgba_to_rgb_white(image: &RgbaImage) -> Vec<u8> {
@@ -523,6 +1103,42 @@ fn resolve_input_source_path(input_path: Option<&str>, tiles: &[ExportTile]) ->
     None
 }
 
+fn resolve_session_file(tiles: &[ExportTile], file_name: &str) -> Option<PathBuf> {
+    tiles
+        .iter()
+        .flat_map(|tile| [tile.path.trim(), tile.original_path.trim()])
+        .filter(|path| !path.is_empty())
+        .filter_map(|path| Path::new(path).parent())
+        .map(|dir| dir.join(file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The shadow layer saved by the last shadow-preserving merge, if it matches the
+/// merged image.
+fn load_shadow_layer(
+    tiles: &[ExportTile],
+    merged_image: &RgbaImage,
+    psd_logs: &mut Vec<String>,
+    verbose_logging: bool,
+) -> Result<Option<RgbaImage>, String> {
+    let Some(path) = resolve_session_file(tiles, SHADOW_LAYER_FILE) else {
+        append_psd_log(psd_logs, verbose_logging, "shadow layer: none saved by the merge");
+        return Ok(None);
+    };
+    let shadow = image::open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?
+        .to_rgba8();
+    if shadow.dimensions() != merged_image.dimensions() {
+        append_psd_log(
+            psd_logs,
+            verbose_logging,
+            "shadow layer: size differs from the merged result, skipped",
+        );
+        return Ok(None);
+    }
+    Ok(Some(shadow))
+}
+
 fn sanitize_path_component(input: &str) -> String {
     let mut out: String = input
         .chars()
@@ -555,6 +1171,7 @@ fn write_psd(
     merged_image: &RgbaImage,
     tile_layers: &[LayerExport],
     overlay_layers: &[OverlayLayerExport],
+    shadow_image: Option<&RgbaImage>,
     psd_logs: &mut Vec<String>,
     verbose_logging: bool,
 ) -> Result<(), String> {
@@ -575,6 +1192,8 @@ fn write_psd(
     );
 
     let mut document = Document::new();
+    // Properties psd-rs cannot set, one entry per pushed layer in push order.
+    let mut layer_props: Vec<Option<PsdLayerProps>> = Vec::new();
 
     let mut input_layer = Layer::new("Input Source");
     input_layer
@@ -586,17 +1205,40 @@ fn write_psd(
         .map_err(|e| e.to_string())?;
     input_layer.set_offset(0, 0);
     document.push(input_layer).map_err(|e| e.to_string())?;
+    layer_props.push(None);
+
+    // With a separate shadow layer, the merged layer carries only the subject.
+    let mut subject_only = None;
+    if let Some(shadow) = shadow_image {
+        let mut shadow_layer = Layer::new("Shadow");
+        shadow_layer
+            .set_image(shadow.as_raw(), shadow.height() as usize, shadow.width() as usize)
+            .map_err(|e| e.to_string())?;
+        shadow_layer.set_offset(0, 0);
+        document.push(shadow_layer).map_err(|e| e.to_string())?;
+        layer_props.push(None);
+
+        let mut subject = merged_image.clone();
+        for (px, shadow_px) in subject.pixels_mut().zip(shadow.pixels()) {
+            if shadow_px[3] > 0 && *px == *shadow_px {
+                *px = Rgba([0, 0, 0, 0]);
+            }
+        }
+        subject_only = Some(subject);
+    }
+    let merged_layer_image = subject_only.as_ref().unwrap_or(merged_image);
 
     let mut merged_layer = Layer::new("Merged Result");
     merged_layer
         .set_image(
-            merged_image.as_raw(),
-            merged_image.height() as usize,
-            merged_image.width() as usize,
+            merged_layer_image.as_raw(),
+            merged_layer_image.height() as usize,
+            merged_layer_image.width() as usize,
         )
         .map_err(|e| e.to_string())?;
     merged_layer.set_offset(0, 0);
     document.push(merged_layer).map_err(|e| e.to_string())?;
+    layer_props.push(None);
 
     for layer in tile_layers {
         let mut psd_layer = Layer::new(format!("Tile r{} c{}", layer.tile.r, layer.tile.c));
@@ -609,6 +1251,7 @@ fn write_psd(
             .map_err(|e| e.to_string())?;
         psd_layer.set_offset(layer.tile.x as usize, layer.tile.y as usize);
         document.push(psd_layer).map_err(|e| e.to_string())?;
+        layer_props.push(None);
     }
 
     for (idx, layer) in overlay_layers.iter().enumerate() {
@@ -620,12 +1263,22 @@ fn write_psd(
             .filter(|value| !value.is_empty())
             .map(|value| value.to_string())
             .unwrap_or_else(|| format!("Generate In Box Overlay {}", idx + 1));
-        let mut psd_layer = Layer::new(format!(
+        let layer_name = format!(
             "{} ({}, v{})",
             overlay_name,
             layer.overlay.id,
             layer.overlay.active_version_index + 1
-        ));
+        );
+        let overlay = &layer.overlay;
+        let props = (!overlay.visible
+            || overlay.opacity < 1.0
+            || overlay.blend_mode != BlendMode::Normal)
+            .then_some(PsdLayerProps {
+                opacity: overlay.opacity,
+                blend: overlay.blend_mode,
+                visible: overlay.visible,
+            });
+        let mut psd_layer = Layer::new(layer_name);
         psd_layer
             .set_image(
                 layer.image.as_raw(),
@@ -635,10 +1288,11 @@ fn write_psd(
             .map_err(|e| e.to_string())?;
         psd_layer.set_offset(layer.overlay.x as usize, layer.overlay.y as usize);
         document.push(psd_layer).map_err(|e| e.to_string())?;
+        layer_props.push(props);
     }
 
     let target = psd_path.to_string_lossy().to_string();
-    match document.save(&target) {
+    let saved = match document.save(&target) {
         Ok(_) => {
             append_psd_log(psd_logs, verbose_logging, "save success: direct write");
             Ok(())
@@ -691,7 +1345,27 @@ fn write_psd(
                 Err(format!("PSD save failed: {}", primary_err))
             }
         }
+    };
+    saved?;
+
+    // psd-rs cannot set opacity, blend mode or visibility, so patch the layer records by
+    // their index. A PSD whose records do not match the pushed layers would be silently
+    // wrong, so drop it.
+    let patched = match psd_layers::apply_layer_props_to_file(psd_path, &layer_props) {
+        Ok(patched) => patched,
+        Err(e) => {
+            let _ = fs::remove_file(psd_path);
+            return Err(format!("PSD layer properties could not be applied: {}", e));
+        }
+    };
+    if patched > 0 {
+        append_psd_log(
+            psd_logs,
+            verbose_logging,
+            &format!("layer properties applied: {}", patched),
+        );
     }
+    Ok(())
 }
 
 #[tauri::command]
@@ -704,6 +1378,7 @@ async fn merge_img(
     key_color: String,
     remove_bg: bool,
     tolerance: u8,
+    options: Option<MergeOptions>,
 ) -> Result<String, String> {
     tauri::async_runtime::spawn_blocking(move || {
         let tile_tuples: Vec<(u32, u32, String)> =
@@ -717,12 +1392,148 @@ async fn merge_img(
             &key_color,
             remove_bg,
             tolerance,
+            &options.unwrap_or_default(),
         )
     })
     .await
     .map_err(|e| e.to_string())?
 }
 
+#[tauri::command]
+async fn merge_img_wrap(
+    tiles: Vec<TileInfo>,
+    original_w: u32,
+    original_h: u32,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    key_color: String,
+    remove_bg: bool,
+    tolerance: u8,
+    options: Option<MergeOptions>,
+) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        seamless::merge_wrap_tiles(
+            tiles,
+            original_w,
+            original_h,
+            rows,
+            cols,
+            overlap_ratio_x,
+            overlap_ratio_y,
+            &key_color,
+            remove_bg,
+            tolerance,
+            &options.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn check_seamless(base64_data: String) -> Result<EdgeMismatch, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let raw = decode_data_url(&base64_data)?;
+        let image = image::load_from_memory(&raw)
+            .map_err(|e| e.to_string())?
+            .to_rgba8();
+        Ok(seamless::edge_mismatch(&image))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn merge_img_rects(
+    tiles: Vec<TileInfo>,
+    original_w: u32,
+    original_h: u32,
+    key_color: String,
+    remove_bg: bool,
+    tolerance: u8,
+    options: Option<MergeOptions>,
+) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        merge_tile_rects(
+            tiles,
+            original_w,
+            original_h,
+            &key_color,
+            remove_bg,
+            tolerance,
+            &options.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// First-pass tile rectangles the seam pass locates its seams from.
+fn seam_rects(tile_rects: &[TileRect]) -> Vec<(u32, u32, u32, u32)> {
+    tile_rects
+        .iter()
+        .map(|tile| (tile.x, tile.y, tile.width, tile.height))
+        .collect()
+}
+
+#[tauri::command]
+async fn split_seam_img(
+    state: tauri::State<'_, AppState>,
+    merged_base64: String,
+    tile_rects: Vec<TileRect>,
+    prefer_jpeg: bool,
+) -> Result<SeamSplitResponse, String> {
+    let td_path = {
+        let mut state_temp = state
+            .temp_dir
+            .lock()
+            .map_err(|_| "Failed to lock state".to_string())?;
+        if state_temp.is_none() {
+            *state_temp = Some(TempDir::new().map_err(|e| e.to_string())?);
+        }
+        state_temp
+            .as_ref()
+            .ok_or_else(|| "Temp directory is unavailable".to_string())?
+            .path()
+            .to_path_buf()
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let raw = decode_data_url(&merged_base64)?;
+        let merged = image::load_from_memory(&raw)
+            .map_err(|e| format!("Failed to decode merged result: {}", e))?
+            .to_rgba8();
+        // The first-pass merge is kept lossless so the untouched areas survive the second pass.
+        let base_path = td_path.join("seam_base.png");
+        save_png_fast(&base_path, &merged)?;
+        let base_path = base_path.to_string_lossy().to_string();
+
+        let rects = seam_rects(&tile_rects);
+        let tiles = seams::split_seam_tiles(&base_path, &rects, prefer_jpeg, &td_path)?;
+        Ok(SeamSplitResponse { tiles, base_path })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn merge_seam_img(
+    base_path: String,
+    tiles: Vec<TileInfo>,
+    tile_rects: Vec<TileRect>,
+    band_width: u32,
+    remove_bg: bool,
+) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let rects = seam_rects(&tile_rects);
+        seams::merge_seam_tiles(&base_path, tiles, &rects, band_width, remove_bg)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 fn seed_tile_outputs_from_base64(base64_data: String, tiles: Vec<SeedTile>) -> Result<u32, String> {
     if tiles.is_empty() {
@@ -757,9 +1568,7 @@ fn seed_tile_outputs_from_base64(base64_data: String, tiles: Vec<SeedTile>) -> R
         if cropped.width() != tile.width || cropped.height() != tile.height {
             crop_w = tile.width.max(1);
             crop_h = tile.height.max(1);
-            cropped = DynamicImage::ImageRgba8(cropped)
-                .resize_exact(crop_w, crop_h, ResizeFilterType::Lanczos3)
-                .to_rgba8();
+            cropped = resize_rgba(&cropped, crop_w, crop_h, ResizeFilterType::Lanczos3);
         }
 
         image_processing::save_rgba_image_auto(path, &cropped)?;
@@ -816,6 +1625,11 @@ fn save_export_bundle_sync(
     save_tiles: bool,
     save_merged: bool,
     save_psd: bool,
+    flatten_overlays: Option<bool>,
+    color_space: Option<ColorSpace>,
+    alpha_cleanup: Option<AlphaCleanupOptions>,
+    merged_alpha_cleaned: Option<bool>,
+    shadow_layer: Option<bool>,
 ) -> Result<SaveBundleResponse, String> {
     if !save_tiles && !save_merged && !save_psd {
         return Err("No export contents selected.".to_string());
@@ -860,6 +1674,20 @@ fn save_export_bundle_sync(
     let psd_path = export_dir.join(format!("{}.psd", stem));
     let mut psd_logs: Vec<String> = Vec::new();
 
+    // With `flatten_overlays`, `merged_base64` is the tile composite without box overlays.
+    let flatten_overlays = flatten_overlays.unwrap_or(false);
+    let color_space = color_space.unwrap_or_default();
+    // The keyed merge gets the same alpha cleanup as `MergeOptions`, once and at full size.
+    // Tile layers and overlays are exported as generated; a merge that already ran
+    // `MergeOptions.alpha_cleanup` is passed with `merged_alpha_cleaned`.
+    let merged_cleanup = alpha_cleanup
+        .filter(|_| remove_bg && !merged_alpha_cleaned.unwrap_or(false));
+    let overlay_layers = if save_psd || (flatten_overlays && save_merged) {
+        decode_overlay_layers(overlays, color_space)?
+    } else {
+        Vec::new()
+    };
+
     let mut merged_img: Option<RgbaImage> = None;
     if save_merged || save_psd {
         append_psd_log(
@@ -872,15 +1700,31 @@ fn save_export_bundle_sync(
             ),
         );
         let merged_raw = decode_data_url(&merged_base64)?;
-        let decoded_merged = image::load_from_memory(&merged_raw)
+        let mut decoded_merged = image::load_from_memory(&merged_raw)
             .map_err(|e| format!("Failed to decode merged result: {}", e))?
             .to_rgba8();
+        if let Some(options) = merged_cleanup.as_ref() {
+            clean_alpha(&mut decoded_merged, options);
+        }
+        if flatten_overlays {
+            decoded_merged =
+                flatten_visible_overlays(&decoded_merged, &overlay_layers, color_space);
+        }
         if save_merged {
             write_image_with_format(&merged_path, &decoded_merged, image_format)?;
         }
         merged_img = Some(decoded_merged);
     }
 
+    let mut guard_mask_path = String::new();
+    if save_merged {
+        if let Some(mask_source) = resolve_session_file(&tiles, SUBJECT_GUARD_MASK_FILE) {
+            let mask_target = export_dir.join(format!("{}_guard_mask.png", stem));
+            fs::copy(&mask_source, &mask_target).map_err(|e| e.to_string())?;
+            guard_mask_path = mask_target.to_string_lossy().to_string();
+        }
+    }
+
     let mut sorted_tiles: Vec<ExportTile> = Vec::new();
     if save_tiles || save_psd {
         sorted_tiles = tiles;
@@ -902,9 +1746,13 @@ fn save_export_bundle_sync(
                 .to_rgba8();
 
             if layer_img.width() != tile.width || layer_img.height() != tile.height {
-                layer_img = DynamicImage::ImageRgba8(layer_img)
-                    .resize_exact(tile.width, tile.height, image::imageops::FilterType::Lanczos3)
-                    .to_rgba8();
+                layer_img = resize_rgba_in(
+                    &layer_img,
+                    tile.width,
+                    tile.height,
+                    image::imageops::FilterType::Lanczos3,
+                    color_space,
+                );
             }
 
             if save_tiles {
@@ -946,9 +1794,13 @@ fn save_export_bundle_sync(
                 })?
                 .to_rgba8();
             if layer_img.width() != layer.width || layer_img.height() != layer.height {
-                layer_img = DynamicImage::ImageRgba8(layer_img)
-                    .resize_exact(layer.width, layer.height, image::imageops::FilterType::Lanczos3)
-                    .to_rgba8();
+                layer_img = resize_rgba_in(
+                    &layer_img,
+                    layer.width,
+                    layer.height,
+                    image::imageops::FilterType::Lanczos3,
+                    color_space,
+                );
             }
             layers.push(LayerExport {
                 tile: ExportTile {
@@ -966,38 +1818,6 @@ fn save_export_bundle_sync(
         }
     }
 
-    let mut overlay_layers: Vec<OverlayLayerExport> = if save_psd {
-        Vec::with_capacity(overlays.len())
-    } else {
-        Vec::new()
-    };
-    if save_psd {
-        let mut sorted_overlays = overlays;
-        sorted_overlays.sort_unstable_by_key(|o| (o.layer_order, o.id));
-        for overlay in sorted_overlays {
-            if overlay.width == 0 || overlay.height == 0 || overlay.data_url.trim().is_empty() {
-                continue;
-            }
-            let overlay_raw = decode_data_url(&overlay.data_url)?;
-            let mut overlay_img = image::load_from_memory(&overlay_raw)
-                .map_err(|e| format!("Failed to decode overlay layer {}: {}", overlay.id, e))?
-                .to_rgba8();
-            if overlay_img.width() != overlay.width || overlay_img.height() != overlay.height {
-                overlay_img = DynamicImage::ImageRgba8(overlay_img)
-                    .resize_exact(
-                        overlay.width,
-                        overlay.height,
-                        image::imageops::FilterType::Lanczos3,
-                    )
-                    .to_rgba8();
-            }
-            overlay_layers.push(OverlayLayerExport {
-                overlay,
-                image: overlay_img,
-            });
-        }
-    }
-
     if save_psd {
         let merged_image = merged_img
             .as_ref()
@@ -1010,21 +1830,28 @@ fn save_export_bundle_sync(
             .to_rgba8();
 
         if source_img.width() != merged_image.width() || source_img.height() != merged_image.height() {
-            source_img = DynamicImage::ImageRgba8(source_img)
-                .resize_exact(
-                    merged_image.width(),
-                    merged_image.height(),
-                    image::imageops::FilterType::Lanczos3,
-                )
-                .to_rgba8();
+            source_img = resize_rgba_in(
+                &source_img,
+                merged_image.width(),
+                merged_image.height(),
+                image::imageops::FilterType::Lanczos3,
+                color_space,
+            );
         }
 
+        let shadow_image = if shadow_layer.unwrap_or(false) && remove_bg {
+            load_shadow_layer(&sorted_tiles, merged_image, &mut psd_logs, verbose_logging)?
+        } else {
+            None
+        };
+
         write_psd(
             &psd_path,
             &source_img,
             merged_image,
             &layers,
             &overlay_layers,
+            shadow_image.as_ref(),
             &mut psd_logs,
             verbose_logging,
         )?;
@@ -1055,6 +1882,7 @@ fn save_export_bundle_sync(
         } else {
             String::new()
         },
+        guard_mask_path,
         tile_count,
         image_format: image_format.name().to_string(),
         psd_logs,
@@ -1077,6 +1905,11 @@ async fn save_export_bundle(
     save_tiles: bool,
     save_merged: bool,
     save_psd: bool,
+    flatten_overlays: Option<bool>,
+    color_space: Option<ColorSpace>,
+    alpha_cleanup: Option<AlphaCleanupOptions>,
+    merged_alpha_cleaned: Option<bool>,
+    shadow_layer: Option<bool>,
 ) -> Result<SaveBundleResponse, String> {
     tauri::async_runtime::spawn_blocking(move || {
         save_export_bundle_sync(
@@ -1094,12 +1927,42 @@ async fn save_export_bundle(
             save_tiles,
             save_merged,
             save_psd,
+            flatten_overlays,
+            color_space,
+            alpha_cleanup,
+            merged_alpha_cleaned,
+            shadow_layer,
         )
     })
     .await
     .map_err(|e| e.to_string())?
 }
 
+#[tauri::command]
+async fn composite_overlays(
+    base64_data: String,
+    overlays: Vec<ExportOverlay>,
+    remove_bg: bool,
+    color_space: Option<ColorSpace>,
+) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let color_space = color_space.unwrap_or_default();
+        let raw = decode_data_url(&base64_data)?;
+        let base = image::load_from_memory(&raw)
+            .map_err(|e| format!("Failed to decode merged base: {}", e))?
+            .to_rgba8();
+        let overlay_layers = decode_overlay_layers(overlays, color_space)?;
+        let flattened = flatten_visible_overlays(&base, &overlay_layers, color_space);
+        if remove_bg {
+            image_processing::encode_png_data_url_fast(&flattened)
+        } else {
+            image_processing::encode_jpeg_data_url_fast(&flattened, 90)
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 fn open_path(path: String) -> Result<(), String> {
     let mut command = if cfg!(target_os = "macos") {
@@ -1131,6 +1994,16 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             split_img,
             merge_img,
+            plan_grid,
+            split_img_outpaint,
+            split_img_wrap,
+            merge_img_wrap,
+            check_seamless,
+            split_img_quadtree,
+            split_img_roi,
+            merge_img_rects,
+            split_seam_img,
+            merge_seam_img,
             crop_img,
             load_image,
             load_image_region,
@@ -1138,10 +2011,19 @@ pub fn run() {
             prepare_tile_paths,
             save_image,
             save_image_resized,
+            solve_dual_background_tile,
+            check_tile_checkerboard,
             save_image_region_blend,
+            save_image_mask_blend,
+            render_region_mask,
+            set_region_mask,
+            clear_region_mask,
+            protected_tile_keys,
+            outpaint_tile_mask,
             seed_tile_outputs_from_base64,
             save_merged_image,
             save_export_bundle,
+            composite_overlays,
             open_path
         ])
         .run(tauri::generate_context!())
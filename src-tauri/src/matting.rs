@@ -0,0 +1,189 @@
+use crate::filters::box_blur;
+use image::{GrayImage, Luma, RgbaImage};
+
+pub const TRIMAP_BACKGROUND: u8 = 0;
+pub const TRIMAP_UNKNOWN: u8 = 128;
+pub const TRIMAP_FOREGROUND: u8 = 255;
+
+/// Guided filter passes over the unknown band.
+const REFINE_PASSES: usize = 4;
+
+/// Alpha refinement against the original photo after keying.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EdgeRefineOptions {
+    /// Half width in pixels of the unknown band around the keyed edge.
+    pub band_width: u32,
+    /// Guided filter window radius.
+    pub radius: u32,
+    /// Guided filter regularisation; larger values give smoother, less photo-driven edges.
+    pub epsilon: f32,
+}
+
+impl Default for EdgeRefineOptions {
+    fn default() -> Self {
+        Self {
+            band_width: 6,
+            radius: 4,
+            epsilon: 1e-3,
+        }
+    }
+}
+
+fn luma(image: &RgbaImage) -> Vec<f32> {
+    image
+        .pixels()
+        .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0)
+        .collect()
+}
+
+/// Trimap from keyed alpha: pixels within `band_width` of both foreground and
+/// background are unknown, the rest keep their side.
+pub fn build_trimap(image: &RgbaImage, band_width: u32) -> GrayImage {
+    let (w, h) = image.dimensions();
+    let foreground: Vec<f32> = image
+        .pixels()
+        .map(|p| if p[3] >= 128 { 1.0 } else { 0.0 })
+        .collect();
+    let background: Vec<f32> = foreground.iter().map(|v| 1.0 - v).collect();
+    let radius = band_width.max(1) as usize;
+    let near_fg = box_blur(&foreground, w as usize, h as usize, 1, radius);
+    let near_bg = box_blur(&background, w as usize, h as usize, 1, radius);
+
+    GrayImage::from_fn(w, h, |x, y| {
+        let idx = (y * w + x) as usize;
+        let value = if near_fg[idx] > 1e-6 && near_bg[idx] > 1e-6 {
+            TRIMAP_UNKNOWN
+        } else if foreground[idx] > 0.0 {
+            TRIMAP_FOREGROUND
+        } else {
+            TRIMAP_BACKGROUND
+        };
+        Luma([value])
+    })
+}
+
+/// Grey-guided filter (He et al.): edge-preserving smoothing of `input` that takes
+/// its edges from `guide`.
+pub fn guided_filter(
+    guide: &[f32],
+    input: &[f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+    epsilon: f32,
+) -> Vec<f32> {
+    let mean = |data: &[f32]| box_blur(data, width, height, 1, radius);
+    let product = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).collect::<Vec<_>>();
+
+    let mean_i = mean(guide);
+    let mean_p = mean(input);
+    let corr_ii = mean(&product(guide, guide));
+    let corr_ip = mean(&product(guide, input));
+
+    let mut a = vec![0.0f32; guide.len()];
+    let mut b = vec![0.0f32; guide.len()];
+    for idx in 0..guide.len() {
+        let var_i = corr_ii[idx] - mean_i[idx] * mean_i[idx];
+        let cov_ip = corr_ip[idx] - mean_i[idx] * mean_p[idx];
+        a[idx] = cov_ip / (var_i + epsilon);
+        b[idx] = mean_p[idx] - a[idx] * mean_i[idx];
+    }
+    let mean_a = mean(&a);
+    let mean_b = mean(&b);
+    (0..guide.len())
+        .map(|idx| mean_a[idx] * guide[idx] + mean_b[idx])
+        .collect()
+}
+
+/// Re-estimates alpha in the unknown band of the keyed `image` with the guided filter,
+/// using `source` (same size) as guidance. Newly revealed pixels take the source colour.
+pub fn refine_alpha(image: &mut RgbaImage, source: &RgbaImage, options: &EdgeRefineOptions) {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 || source.dimensions() != (w, h) {
+        return;
+    }
+    let trimap = build_trimap(image, options.band_width);
+    if !trimap.pixels().any(|p| p[0] == TRIMAP_UNKNOWN) {
+        return;
+    }
+
+    let guide = luma(source);
+    let mut refined: Vec<f32> = image.pixels().map(|p| p[3] as f32 / 255.0).collect();
+    // Known pixels are pinned after every pass so alpha propagates from both sides of
+    // the band along the photo's edges.
+    for _ in 0..REFINE_PASSES {
+        refined = guided_filter(
+            &guide,
+            &refined,
+            w as usize,
+            h as usize,
+            options.radius.max(1) as usize,
+            options.epsilon.max(1e-6),
+        );
+        for (value, region) in refined.iter_mut().zip(trimap.pixels()) {
+            match region[0] {
+                TRIMAP_FOREGROUND => *value = 1.0,
+                TRIMAP_BACKGROUND => *value = 0.0,
+                _ => *value = value.clamp(0.0, 1.0),
+            }
+        }
+    }
+
+    for (idx, (px, region)) in image.pixels_mut().zip(trimap.pixels()).enumerate() {
+        if region[0] != TRIMAP_UNKNOWN {
+            continue;
+        }
+        let value = (refined[idx] * 255.0).round() as u8;
+        if px[3] == 0 && value > 0 {
+            let (x, y) = (idx as u32 % w, idx as u32 / w);
+            let colour = source.get_pixel(x, y);
+            px.0 = [colour[0], colour[1], colour[2], value];
+        } else if value == 0 {
+            px.0 = [0, 0, 0, 0];
+        } else {
+            px[3] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_refine_alpha_moves_offset_edge_towards_photo() {
+        // The photo edge is at x = 16, the keyed alpha edge two pixels to the right.
+        let source = RgbaImage::from_fn(32, 8, |x, _| {
+            if x < 16 {
+                Rgba([20, 20, 20, 255])
+            } else {
+                Rgba([230, 230, 230, 255])
+            }
+        });
+        let mut keyed = RgbaImage::from_fn(32, 8, |x, _| {
+            if x < 18 {
+                Rgba([20, 20, 20, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+
+        let trimap = build_trimap(&keyed, 3);
+        assert_eq!(trimap.get_pixel(0, 4)[0], TRIMAP_FOREGROUND);
+        assert_eq!(trimap.get_pixel(17, 4)[0], TRIMAP_UNKNOWN);
+        assert_eq!(trimap.get_pixel(31, 4)[0], TRIMAP_BACKGROUND);
+
+        let options = EdgeRefineOptions {
+            band_width: 3,
+            radius: 2,
+            epsilon: 1e-4,
+        };
+        refine_alpha(&mut keyed, &source, &options);
+        assert!(keyed.get_pixel(15, 4)[3] > 200);
+        assert!(keyed.get_pixel(16, 4)[3] < 128);
+        assert!(keyed.get_pixel(17, 4)[3] < 128);
+        assert_eq!(keyed.get_pixel(31, 4)[3], 0);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::filters::box_blur;
+use crate::flood_fill::scanline_fill;
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Alpha below this counts as background when looking for holes.
+const HOLE_ALPHA: u8 = 128;
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MorphologyOp {
+    Erode,
+    Dilate,
+    /// Erode then dilate: removes specks thinner than the radius.
+    Open,
+    /// Dilate then erode: closes gaps and pinholes narrower than the radius.
+    Close,
+}
+
+/// Alpha post-processing applied after keying.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AlphaCleanupOptions {
+    pub operation: Option<MorphologyOp>,
+    /// Square structuring element radius in pixels.
+    pub radius: u32,
+    /// Visible islands smaller than this many pixels are removed; 0 disables.
+    pub min_island_area: u32,
+    /// Enclosed holes smaller than this many pixels are filled; 0 disables.
+    pub max_hole_area: u32,
+    /// Inward feather radius for the alpha edge; 0 disables.
+    pub feather: u32,
+}
+
+impl Default for AlphaCleanupOptions {
+    fn default() -> Self {
+        Self {
+            operation: None,
+            radius: 1,
+            min_island_area: 0,
+            max_hole_area: 0,
+            feather: 0,
+        }
+    }
+}
+
+/// Sliding-window min or max of `(alpha, source index)` pairs along one line.
+fn sliding_extreme(line: &[(u8, u32)], radius: usize, dilate: bool) -> Vec<(u8, u32)> {
+    let n = line.len();
+    let wins = |a: u8, b: u8| if dilate { a >= b } else { a <= b };
+    let mut window: VecDeque<usize> = VecDeque::new();
+    let mut out = Vec::with_capacity(n);
+    let mut next = 0;
+    for i in 0..n {
+        while next < n && next <= i + radius {
+            while window
+                .back()
+                .is_some_and(|&j| wins(line[next].0, line[j].0))
+            {
+                window.pop_back();
+            }
+            window.push_back(next);
+            next += 1;
+        }
+        while window.front().is_some_and(|&j| j + radius < i) {
+            window.pop_front();
+        }
+        out.push(line[window[0]]);
+    }
+    out
+}
+
+/// Grey-level erosion or dilation of the alpha channel with a square window.
+/// Pixels revealed by dilation take the colour of the pixel that revealed them.
+fn morph(image: &mut RgbaImage, radius: u32, dilate: bool) {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    if radius == 0 || w == 0 || h == 0 {
+        return;
+    }
+    let radius = radius as usize;
+    let start: Vec<(u8, u32)> = image
+        .pixels()
+        .enumerate()
+        .map(|(idx, p)| (p[3], idx as u32))
+        .collect();
+    let rows: Vec<(u8, u32)> = start
+        .par_chunks(w)
+        .flat_map_iter(|row| sliding_extreme(row, radius, dilate))
+        .collect();
+    let columns: Vec<Vec<(u8, u32)>> = (0..w)
+        .into_par_iter()
+        .map(|x| {
+            let column: Vec<(u8, u32)> = (0..h).map(|y| rows[y * w + x]).collect();
+            sliding_extreme(&column, radius, dilate)
+        })
+        .collect();
+
+    let raw: &mut [u8] = image.as_mut();
+    for (x, column) in columns.iter().enumerate() {
+        for (y, &(alpha, source)) in column.iter().enumerate() {
+            let idx = (y * w + x) * 4;
+            if alpha == 0 {
+                raw[idx..idx + 4].fill(0);
+                continue;
+            }
+            // Revealed pixels have no colour of their own; the source pixel kept its alpha.
+            if raw[idx + 3] == 0 {
+                let source = source as usize * 4;
+                raw.copy_within(source..source + 3, idx);
+            }
+            raw[idx + 3] = alpha;
+        }
+    }
+}
+
+/// Removes visible islands below `min_island_area` and fills enclosed holes below
+/// `max_hole_area` with the mean colour of the pixels bordering them.
+fn filter_components(image: &mut RgbaImage, min_island_area: u32, max_hole_area: u32) {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let area = |spans: &[(usize, usize)]| -> usize { spans.iter().map(|(s, e)| e - s + 1).sum() };
+
+    if min_island_area > 0 {
+        let visible: Vec<bool> = image.pixels().map(|p| p[3] > 0).collect();
+        let mut visited = vec![false; visible.len()];
+        for idx in 0..visible.len() {
+            if !visible[idx] || visited[idx] {
+                continue;
+            }
+            let spans = scanline_fill(&visible, &mut visited, w, h, idx % w, idx / w);
+            if area(&spans) < min_island_area as usize {
+                let raw: &mut [u8] = image.as_mut();
+                for (start, end) in spans {
+                    raw[start * 4..(end + 1) * 4].fill(0);
+                }
+            }
+        }
+    }
+
+    if max_hole_area > 0 {
+        let clear: Vec<bool> = image.pixels().map(|p| p[3] < HOLE_ALPHA).collect();
+        let mut visited = vec![false; clear.len()];
+        for idx in 0..clear.len() {
+            if !clear[idx] || visited[idx] {
+                continue;
+            }
+            let spans = scanline_fill(&clear, &mut visited, w, h, idx % w, idx / w);
+            let touches_border = spans.iter().any(|&(start, end)| {
+                start % w == 0 || end % w == w - 1 || start / w == 0 || start / w == h - 1
+            });
+            if touches_border || area(&spans) >= max_hole_area as usize {
+                continue;
+            }
+
+            // Spans are maximal, so the pixels either side of each one are opaque.
+            let raw: &mut [u8] = image.as_mut();
+            let mut sum = [0u32; 3];
+            for &(start, end) in &spans {
+                for side in [start - 1, end + 1] {
+                    for ch in 0..3 {
+                        sum[ch] += raw[side * 4 + ch] as u32;
+                    }
+                }
+            }
+            let count = (spans.len() * 2) as u32;
+            let fill = [sum[0] / count, sum[1] / count, sum[2] / count];
+            for (start, end) in spans {
+                for px in raw[start * 4..(end + 1) * 4].chunks_exact_mut(4) {
+                    px.copy_from_slice(&[fill[0] as u8, fill[1] as u8, fill[2] as u8, 255]);
+                }
+            }
+        }
+    }
+}
+
+/// Softens the alpha edge inwards so no colour has to be invented outside it.
+fn feather_alpha(image: &mut RgbaImage, radius: u32) {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let alpha: Vec<f32> = image.pixels().map(|p| p[3] as f32).collect();
+    let blurred = box_blur(&alpha, w, h, 1, radius as usize);
+    for (px, value) in image.pixels_mut().zip(blurred) {
+        px[3] = px[3].min(value.round().clamp(0.0, 255.0) as u8);
+        if px[3] == 0 {
+            px.0 = [0, 0, 0, 0];
+        }
+    }
+}
+
+/// Morphology, then island and hole filtering, then feathering.
+pub fn clean_alpha(image: &mut RgbaImage, options: &AlphaCleanupOptions) {
+    if image.width() == 0 || image.height() == 0 {
+        return;
+    }
+    match options.operation {
+        Some(MorphologyOp::Erode) => morph(image, options.radius, false),
+        Some(MorphologyOp::Dilate) => morph(image, options.radius, true),
+        Some(MorphologyOp::Open) => {
+            morph(image, options.radius, false);
+            morph(image, options.radius, true);
+        }
+        Some(MorphologyOp::Close) => {
+            morph(image, options.radius, true);
+            morph(image, options.radius, false);
+        }
+        None => {}
+    }
+    filter_components(image, options.min_island_area, options.max_hole_area);
+    if options.feather > 0 {
+        feather_alpha(image, options.feather);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn subject() -> RgbaImage {
+        // 8x8 opaque square with a one-pixel hole, plus a stray speck in the corner.
+        RgbaImage::from_fn(16, 16, |x, y| {
+            let square = (4..12).contains(&x) && (4..12).contains(&y);
+            let hole = x == 7 && y == 7;
+            let speck = x == 1 && y == 1;
+            if (square && !hole) || speck {
+                Rgba([200, 40, 40, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    #[test]
+    fn test_cleanup_removes_specks_and_fills_holes() {
+        let mut image = subject();
+        let options = AlphaCleanupOptions {
+            min_island_area: 4,
+            max_hole_area: 4,
+            ..AlphaCleanupOptions::default()
+        };
+        clean_alpha(&mut image, &options);
+        assert_eq!(image.get_pixel(1, 1)[3], 0);
+        assert_eq!(*image.get_pixel(7, 7), Rgba([200, 40, 40, 255]));
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+
+        let mut closed = subject();
+        let options = AlphaCleanupOptions {
+            operation: Some(MorphologyOp::Close),
+            ..AlphaCleanupOptions::default()
+        };
+        clean_alpha(&mut closed, &options);
+        assert_eq!(*closed.get_pixel(7, 7), Rgba([200, 40, 40, 255]));
+        assert_eq!(closed.get_pixel(3, 3)[3], 0);
+
+        let mut opened = subject();
+        let options = AlphaCleanupOptions {
+            operation: Some(MorphologyOp::Open),
+            feather: 1,
+            ..AlphaCleanupOptions::default()
+        };
+        clean_alpha(&mut opened, &options);
+        assert_eq!(opened.get_pixel(1, 1)[3], 0);
+        assert_eq!(opened.get_pixel(9, 9)[3], 255);
+        assert!(opened.get_pixel(4, 9)[3] < 255);
+    }
+}
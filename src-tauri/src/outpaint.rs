@@ -0,0 +1,175 @@
+use crate::image_processing::{
+    prepare_split_source, save_image_fast, write_tile_files, GridGeometry, TileInfo,
+};
+use image::{GrayImage, Luma, RgbaImage};
+use rayon::prelude::*;
+use std::path::Path;
+
+pub const OUTPAINT_MASK_FILE: &str = "outpaint_mask.png";
+
+/// Pixels added to each side of the canvas.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CanvasExtension {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl CanvasExtension {
+    fn is_empty(&self) -> bool {
+        self.top == 0 && self.right == 0 && self.bottom == 0 && self.left == 0
+    }
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct OutpaintTile {
+    #[serde(flatten)]
+    pub tile: TileInfo,
+    /// Grayscale mask of the tile (255 = unknown, to be generated). `None` when the
+    /// tile lies entirely inside the source and needs no outpainting.
+    pub mask_path: Option<String>,
+    /// Share of the tile's pixels that are unknown.
+    pub unknown_ratio: f32,
+}
+
+/// Places `source` on a larger canvas. Unknown pixels repeat the nearest source edge
+/// so the model sees plausible colours, and are marked in the returned mask.
+pub fn extend_canvas(source: &RgbaImage, extension: &CanvasExtension) -> (RgbaImage, GrayImage) {
+    let (w, h) = source.dimensions();
+    let canvas_w = w + extension.left + extension.right;
+    let canvas_h = h + extension.top + extension.bottom;
+    let inside = |x: u32, y: u32| {
+        x >= extension.left && x < extension.left + w && y >= extension.top && y < extension.top + h
+    };
+
+    let canvas = RgbaImage::from_fn(canvas_w, canvas_h, |x, y| {
+        let sx = x.saturating_sub(extension.left).min(w - 1);
+        let sy = y.saturating_sub(extension.top).min(h - 1);
+        *source.get_pixel(sx, sy)
+    });
+    let mask = GrayImage::from_fn(canvas_w, canvas_h, |x, y| {
+        if inside(x, y) {
+            Luma([0])
+        } else {
+            Luma([255])
+        }
+    });
+    (canvas, mask)
+}
+
+/// Crops the unknown mask to a tile and returns it with the tile's unknown share.
+/// `None` when the tile has no unknown pixels.
+pub fn tile_unknown_mask(
+    mask: &GrayImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Option<(GrayImage, f32)> {
+    let tile_mask = image::imageops::crop_imm(mask, x, y, width, height).to_image();
+    let unknown = tile_mask.pixels().filter(|p| p[0] > 0).count();
+    if unknown == 0 {
+        return None;
+    }
+    let ratio = unknown as f32 / (tile_mask.width() * tile_mask.height()) as f32;
+    Some((tile_mask, ratio))
+}
+
+/// Splits a padded canvas into the usual grid so the result merges with `merge_tiles`
+/// at the extended size. Tiles touching the new border get a per-tile unknown mask.
+pub fn split_image_outpaint(
+    input_path: &str,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    extension: &CanvasExtension,
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<(Vec<OutpaintTile>, u32, u32, String), String> {
+    if rows == 0 || cols == 0 {
+        return Err("Rows and cols must be greater than zero".to_string());
+    }
+    if extension.is_empty() {
+        return Err("Canvas extension must add pixels on at least one side".to_string());
+    }
+
+    let (source, image_format, new_input_path) =
+        prepare_split_source(input_path, prefer_jpeg, output_dir)?;
+    if source.width() == 0 || source.height() == 0 {
+        return Err("Image dimensions must be greater than zero".to_string());
+    }
+    let (canvas, mask) = extend_canvas(&source, extension);
+    let (w, h) = canvas.dimensions();
+
+    // The extended canvas replaces the original source so merge fallbacks match its size.
+    save_image_fast(Path::new(&new_input_path), &canvas, image_format)?;
+    mask.save(output_dir.join(OUTPAINT_MASK_FILE))
+        .map_err(|e| format!("Failed to save outpaint mask: {}", e))?;
+
+    let grid = GridGeometry::new(w, h, rows, cols, overlap_ratio_x, overlap_ratio_y)?;
+    let tile_configs: Vec<_> = grid
+        .tile_keys()
+        .into_iter()
+        .filter_map(|(r, c)| {
+            let (x, y, tw, th) = grid.tile_rect(r, c)?;
+            Some((r, c, x, y, tw, th))
+        })
+        .collect();
+    let tiles = write_tile_files(&canvas, tile_configs, image_format, output_dir, "")?;
+
+    let outpaint_tiles = tiles
+        .into_par_iter()
+        .map(|tile| {
+            let Some((tile_mask, unknown_ratio)) =
+                tile_unknown_mask(&mask, tile.x, tile.y, tile.width, tile.height)
+            else {
+                return Ok(OutpaintTile {
+                    tile,
+                    mask_path: None,
+                    unknown_ratio: 0.0,
+                });
+            };
+
+            let mask_path = output_dir.join(format!("mask_tile_{}_{}.png", tile.r, tile.c));
+            tile_mask
+                .save(&mask_path)
+                .map_err(|e| format!("Failed to save tile mask: {}", e))?;
+            Ok(OutpaintTile {
+                tile,
+                mask_path: Some(mask_path.to_string_lossy().to_string()),
+                unknown_ratio,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok((outpaint_tiles, w, h, new_input_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_extend_canvas_marks_only_new_pixels_unknown() {
+        let source = RgbaImage::from_pixel(4, 3, Rgba([10, 20, 30, 255]));
+        let extension = CanvasExtension {
+            top: 1,
+            right: 2,
+            bottom: 0,
+            left: 3,
+        };
+        let (canvas, mask) = extend_canvas(&source, &extension);
+
+        assert_eq!(canvas.dimensions(), (9, 4));
+        assert_eq!(mask.get_pixel(3, 1)[0], 0);
+        assert_eq!(mask.get_pixel(6, 3)[0], 0);
+        assert_eq!(mask.get_pixel(2, 1)[0], 255);
+        assert_eq!(mask.get_pixel(4, 0)[0], 255);
+        assert_eq!(mask.get_pixel(7, 2)[0], 255);
+        assert_eq!(*canvas.get_pixel(0, 0), Rgba([10, 20, 30, 255]));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::compositor::BlendMode;
+use std::fs;
+use std::path::Path;
+
+/// Layer properties `psd-rs` cannot set, patched into the saved file by layer index.
+#[derive(Clone, Copy, Debug)]
+pub struct PsdLayerProps {
+    pub opacity: f32,
+    pub blend: BlendMode,
+    pub visible: bool,
+}
+
+/// Hidden bit of the layer record flags byte.
+const FLAG_HIDDEN: u8 = 0x02;
+
+fn blend_key(mode: BlendMode) -> &'static [u8; 4] {
+    match mode {
+        BlendMode::Normal => b"norm",
+        BlendMode::Multiply => b"mul ",
+        BlendMode::Screen => b"scrn",
+        BlendMode::Overlay => b"over",
+        BlendMode::SoftLight => b"sLit",
+        BlendMode::Darken => b"dark",
+        BlendMode::Lighten => b"lite",
+        BlendMode::Color => b"colr",
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<usize, String> {
+        let start = self.pos;
+        let end = start
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or("Truncated PSD file")?;
+        self.pos = end;
+        Ok(start)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let at = self.take(2)?;
+        Ok(u16::from_be_bytes([self.data[at], self.data[at + 1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let at = self.take(4)?;
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&self.data[at..at + 4]);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let at = self.take(8)?;
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&self.data[at..at + 8]);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// Section length: 4 bytes in PSD, 8 bytes in PSB.
+    fn length(&mut self, psb: bool) -> Result<usize, String> {
+        if psb {
+            Ok(self.u64()? as usize)
+        } else {
+            Ok(self.u32()? as usize)
+        }
+    }
+}
+
+/// Rewrites blend mode, opacity and visibility of the layer records. `props` holds one
+/// entry per layer in the order they were pushed, which is the order psd-cpp writes the
+/// records in; `None` leaves a record as saved. Returns the number of layers patched.
+pub fn apply_layer_props(
+    data: &mut [u8],
+    props: &[Option<PsdLayerProps>],
+) -> Result<usize, String> {
+    if props.iter().all(Option::is_none) {
+        return Ok(0);
+    }
+
+    let mut reader = Reader { data, pos: 0 };
+    let signature = reader.take(4)?;
+    if &data[signature..signature + 4] != b"8BPS" {
+        return Err("Not a PSD file".to_string());
+    }
+    let psb = reader.u16()? == 2;
+    reader.take(20)?;
+
+    let color_mode_len = reader.u32()? as usize;
+    reader.take(color_mode_len)?;
+    let resources_len = reader.u32()? as usize;
+    reader.take(resources_len)?;
+
+    let layer_mask_len = reader.length(psb)?;
+    if layer_mask_len == 0 {
+        return Err("PSD has no layer records to patch".to_string());
+    }
+    let layer_info_len = reader.length(psb)?;
+    if layer_info_len == 0 {
+        return Err("PSD has no layer records to patch".to_string());
+    }
+    let layer_count = (reader.u16()? as i16).unsigned_abs();
+
+    // Offset of the blend key of each layer record.
+    let mut records: Vec<usize> = Vec::with_capacity(layer_count as usize);
+    for _ in 0..layer_count {
+        reader.take(16)?;
+        let channels = reader.u16()? as usize;
+        reader.take(channels * if psb { 10 } else { 6 })?;
+        let blend_signature = reader.take(4)?;
+        if &data[blend_signature..blend_signature + 4] != b"8BIM" {
+            return Err("Unexpected layer record signature in PSD".to_string());
+        }
+        let key_offset = reader.take(8)?;
+
+        let extra_len = reader.u32()? as usize;
+        reader.take(extra_len)?;
+        records.push(key_offset);
+    }
+    if records.len() != props.len() {
+        return Err(format!(
+            "PSD has {} layer records but {} layers were written",
+            records.len(),
+            props.len()
+        ));
+    }
+
+    let mut patched = 0;
+    for (key_offset, layer) in records.into_iter().zip(props) {
+        let Some(layer) = layer else {
+            continue;
+        };
+        data[key_offset..key_offset + 4].copy_from_slice(blend_key(layer.blend));
+        data[key_offset + 4] = (layer.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let flags = &mut data[key_offset + 6];
+        if layer.visible {
+            *flags &= !FLAG_HIDDEN;
+        } else {
+            *flags |= FLAG_HIDDEN;
+        }
+        patched += 1;
+    }
+    Ok(patched)
+}
+
+pub fn apply_layer_props_to_file(
+    path: &Path,
+    props: &[Option<PsdLayerProps>],
+) -> Result<usize, String> {
+    if props.iter().all(Option::is_none) {
+        return Ok(0);
+    }
+    let mut data = fs::read(path).map_err(|e| e.to_string())?;
+    let patched = apply_layer_props(&mut data, props)?;
+    fs::write(path, &data).map_err(|e| e.to_string())?;
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer_record(name: &str) -> Vec<u8> {
+        let mut record = vec![0u8; 16];
+        record.extend_from_slice(&1u16.to_be_bytes());
+        record.extend_from_slice(&[0, 0, 0, 0, 0, 2]);
+        record.extend_from_slice(b"8BIMnorm");
+        record.extend_from_slice(&[255, 0, 0, 0]);
+
+        let mut extra = vec![0, 0, 0, 0, 0, 0, 0, 0, name.len() as u8];
+        extra.extend_from_slice(name.as_bytes());
+        while (extra.len() - 8) % 4 != 0 {
+            extra.push(0);
+        }
+        record.extend_from_slice(&(extra.len() as u32).to_be_bytes());
+        record.extend_from_slice(&extra);
+        record
+    }
+
+    #[test]
+    fn test_apply_layer_props_patches_records_by_index() {
+        let mut layer_info = 2u16.to_be_bytes().to_vec();
+        layer_info.extend(layer_record("Merged Result"));
+        layer_info.extend(layer_record("Box (1, v1)"));
+
+        let mut psd = b"8BPS".to_vec();
+        psd.extend_from_slice(&1u16.to_be_bytes());
+        psd.extend_from_slice(&[0u8; 20]);
+        psd.extend_from_slice(&[0u8; 8]);
+        psd.extend_from_slice(&((layer_info.len() + 4) as u32).to_be_bytes());
+        psd.extend_from_slice(&(layer_info.len() as u32).to_be_bytes());
+        psd.extend_from_slice(&layer_info);
+
+        let overlay = PsdLayerProps {
+            opacity: 0.5,
+            blend: BlendMode::SoftLight,
+            visible: false,
+        };
+        assert_eq!(
+            apply_layer_props(&mut psd, &[None, Some(overlay)]).unwrap(),
+            1
+        );
+
+        let text = psd.windows(8).position(|w| w == b"8BIMsLit").unwrap();
+        assert_eq!(psd[text + 8], 128);
+        assert_eq!(psd[text + 10] & FLAG_HIDDEN, FLAG_HIDDEN);
+        assert_eq!(psd.windows(8).filter(|w| w == b"8BIMnorm").count(), 1);
+
+        // A layer count that does not match the written records must not be guessed at.
+        assert!(apply_layer_props(&mut psd, &[None, None, Some(overlay)]).is_err());
+    }
+}
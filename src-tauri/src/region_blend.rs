@@ -0,0 +1,507 @@
+use crate::filters::{lerp_premultiplied, resize_rgba};
+use crate::image_processing::{decode_data_url, save_rgba_image_auto, TileInfo};
+use image::imageops::FilterType as ResizeFilterType;
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// Samples per axis used to anti-alias polygon edges.
+const POLYGON_SUPERSAMPLE: u32 = 4;
+
+/// Shape of a Generate In Box region. Polygon points are in full-image coordinates.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RegionShape {
+    Rect,
+    /// Closed polygon or lasso path.
+    Polygon {
+        points: Vec<(f32, f32)>,
+    },
+    /// Brush bitmap covering the region box. Alpha is used when present, luma otherwise.
+    Bitmap {
+        #[serde(rename = "dataUrl")]
+        data_url: String,
+    },
+}
+
+/// Region-sized coverage mask (255 = take the generated pixel).
+pub fn region_mask(
+    shape: &RegionShape,
+    region_x: u32,
+    region_y: u32,
+    width: u32,
+    height: u32,
+) -> Result<GrayImage, String> {
+    match shape {
+        RegionShape::Rect => Ok(GrayImage::from_pixel(width, height, Luma([255]))),
+        RegionShape::Polygon { points } => Ok(rasterize_polygon(
+            points,
+            region_x as f32,
+            region_y as f32,
+            width,
+            height,
+        )),
+        RegionShape::Bitmap { data_url } => {
+            let raw = decode_data_url(data_url)?;
+            let bitmap = image::load_from_memory(&raw)
+                .map_err(|e| format!("Failed to decode region mask: {}", e))?
+                .resize_exact(width, height, ResizeFilterType::Triangle);
+            Ok(bitmap_coverage(&bitmap))
+        }
+    }
+}
+
+fn bitmap_coverage(bitmap: &DynamicImage) -> GrayImage {
+    if bitmap.color().has_alpha() {
+        let rgba = bitmap.to_rgba8();
+        GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            Luma([rgba.get_pixel(x, y)[3]])
+        })
+    } else {
+        bitmap.to_luma8()
+    }
+}
+
+/// Even-odd scanline fill with `POLYGON_SUPERSAMPLE`^2 samples per pixel.
+fn rasterize_polygon(
+    points: &[(f32, f32)],
+    origin_x: f32,
+    origin_y: f32,
+    width: u32,
+    height: u32,
+) -> GrayImage {
+    let mut mask = GrayImage::new(width, height);
+    if points.len() < 3 {
+        return mask;
+    }
+
+    let ss = POLYGON_SUPERSAMPLE;
+    let step = 1.0 / ss as f32;
+    let sample_cols = (width * ss) as i64;
+    let mut counts = vec![0u32; width as usize];
+    let mut crossings: Vec<f32> = Vec::new();
+
+    for py in 0..height {
+        counts.iter_mut().for_each(|c| *c = 0);
+        for sub in 0..ss {
+            let sy = origin_y + py as f32 + (sub as f32 + 0.5) * step;
+            crossings.clear();
+            for (i, &(x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(i + 1) % points.len()];
+                if (y1 <= sy) != (y2 <= sy) {
+                    crossings.push(x1 + (sy - y1) / (y2 - y1) * (x2 - x1));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+
+            for pair in crossings.chunks_exact(2) {
+                // Sample column i sits at origin_x + (i + 0.5) * step.
+                let first = (((pair[0] - origin_x) / step) - 0.5).ceil() as i64;
+                let last = (((pair[1] - origin_x) / step) - 0.5).ceil() as i64;
+                for i in first.max(0)..last.min(sample_cols) {
+                    counts[(i / ss as i64) as usize] += 1;
+                }
+            }
+        }
+        for (px, &count) in counts.iter().enumerate() {
+            let value = (count * 255 + ss * ss / 2) / (ss * ss);
+            mask.put_pixel(px as u32, py, Luma([value.min(255) as u8]));
+        }
+    }
+    mask
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionBlendMode {
+    /// Paste generated pixels as they are.
+    #[default]
+    Paste,
+    /// Fade the overlay in over `feather_width` pixels from the mask edge.
+    Feather,
+    /// Gradient-domain blend: keep the overlay's detail but match its border to the base.
+    Poisson,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RegionBlendOptions {
+    pub mode: RegionBlendMode,
+    /// Distance in pixels over which the mask edge fades out. Also applied in Poisson mode.
+    pub feather_width: u32,
+}
+
+/// Chamfer distance from each pixel to the nearest unmasked pixel or the region border.
+fn distance_to_edge(mask: &GrayImage) -> Vec<f32> {
+    let (w, h) = (mask.width() as usize, mask.height() as usize);
+    let far = (w + h) as f32;
+    let mut dist: Vec<f32> = mask
+        .pixels()
+        .map(|p| if p[0] == 0 { 0.0 } else { far })
+        .collect();
+    let get = |dist: &[f32], x: isize, y: isize| -> f32 {
+        if x < 0 || y < 0 || x >= w as isize || y >= h as isize {
+            0.0
+        } else {
+            dist[y as usize * w + x as usize]
+        }
+    };
+    let (straight, diagonal) = (1.0, std::f32::consts::SQRT_2);
+
+    for y in 0..h as isize {
+        for x in 0..w as isize {
+            let idx = y as usize * w + x as usize;
+            let best = (get(&dist, x - 1, y) + straight)
+                .min(get(&dist, x, y - 1) + straight)
+                .min(get(&dist, x - 1, y - 1) + diagonal)
+                .min(get(&dist, x + 1, y - 1) + diagonal);
+            dist[idx] = dist[idx].min(best);
+        }
+    }
+    for y in (0..h as isize).rev() {
+        for x in (0..w as isize).rev() {
+            let idx = y as usize * w + x as usize;
+            let best = (get(&dist, x + 1, y) + straight)
+                .min(get(&dist, x, y + 1) + straight)
+                .min(get(&dist, x + 1, y + 1) + diagonal)
+                .min(get(&dist, x - 1, y + 1) + diagonal);
+            dist[idx] = dist[idx].min(best);
+        }
+    }
+    dist
+}
+
+/// Fades the mask inwards over `width` pixels, so nothing outside the shape is touched.
+pub fn feather_mask(mask: &mut GrayImage, width: u32) {
+    if width == 0 {
+        return;
+    }
+    let dist = distance_to_edge(mask);
+    for (idx, px) in mask.pixels_mut().enumerate() {
+        let ramp = (dist[idx] / width as f32).min(1.0);
+        px[0] = (px[0] as f32 * ramp).round() as u8;
+    }
+}
+
+/// Smooth (harmonic) fill of the unfixed entries of `values`, keeping fixed ones.
+/// A coarse solve seeds each level so large regions converge in few sweeps.
+fn solve_membrane(values: &mut [f32], fixed: &[bool], w: usize, h: usize) {
+    const COARSE_LIMIT: usize = 32;
+    const SWEEPS: usize = 60;
+
+    if w > COARSE_LIMIT && h > COARSE_LIMIT {
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        let mut coarse = vec![0.0f32; cw * ch];
+        let mut coarse_fixed = vec![false; cw * ch];
+        for cy in 0..ch {
+            for cx in 0..cw {
+                let (mut sum, mut count) = (0.0, 0);
+                for y in (cy * 2)..(cy * 2 + 2).min(h) {
+                    for x in (cx * 2)..(cx * 2 + 2).min(w) {
+                        if fixed[y * w + x] {
+                            sum += values[y * w + x];
+                            count += 1;
+                        }
+                    }
+                }
+                if count > 0 {
+                    coarse[cy * cw + cx] = sum / count as f32;
+                    coarse_fixed[cy * cw + cx] = true;
+                }
+            }
+        }
+        solve_membrane(&mut coarse, &coarse_fixed, cw, ch);
+        for y in 0..h {
+            for x in 0..w {
+                if !fixed[y * w + x] {
+                    values[y * w + x] = coarse[(y / 2) * cw + x / 2];
+                }
+            }
+        }
+    }
+
+    let sweeps = if w > COARSE_LIMIT && h > COARSE_LIMIT {
+        SWEEPS
+    } else {
+        SWEEPS * 10
+    };
+    let omega = 1.8;
+    for _ in 0..sweeps {
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y * w + x;
+                if fixed[idx] {
+                    continue;
+                }
+                let (mut sum, mut count) = (0.0, 0.0);
+                if x > 0 {
+                    sum += values[idx - 1];
+                    count += 1.0;
+                }
+                if x + 1 < w {
+                    sum += values[idx + 1];
+                    count += 1.0;
+                }
+                if y > 0 {
+                    sum += values[idx - w];
+                    count += 1.0;
+                }
+                if y + 1 < h {
+                    sum += values[idx + w];
+                    count += 1.0;
+                }
+                values[idx] += omega * (sum / count - values[idx]);
+            }
+        }
+    }
+}
+
+/// Gradient-domain blend of `generated` onto `target` (both region-sized).
+///
+/// Pixels outside the mask and the region's outer ring act as the boundary. The
+/// overlay keeps its own gradients while its colour offset is interpolated from
+/// the boundary mismatch, which removes visible box outlines.
+pub fn poisson_blend(generated: &RgbaImage, target: &RgbaImage, mask: &GrayImage) -> RgbaImage {
+    let (w, h) = generated.dimensions();
+    if target.dimensions() != (w, h) || w < 3 || h < 3 {
+        return generated.clone();
+    }
+    let (wu, hu) = (w as usize, h as usize);
+    let fixed: Vec<bool> = mask
+        .enumerate_pixels()
+        .map(|(x, y, p)| p[0] == 0 || x == 0 || y == 0 || x == w - 1 || y == h - 1)
+        .collect();
+
+    let offsets: Vec<Vec<f32>> = (0..3)
+        .into_par_iter()
+        .map(|ch| {
+            let mut values: Vec<f32> = generated
+                .pixels()
+                .zip(target.pixels())
+                .zip(fixed.iter())
+                .map(|((g, t), &is_fixed)| {
+                    if is_fixed {
+                        t[ch] as f32 - g[ch] as f32
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            solve_membrane(&mut values, &fixed, wu, hu);
+            values
+        })
+        .collect();
+
+    let mut out = generated.clone();
+    for (idx, px) in out.pixels_mut().enumerate() {
+        for (ch, offset) in offsets.iter().enumerate() {
+            px[ch] = (px[ch] as f32 + offset[idx]).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out
+}
+
+/// Applies the blend options to a region: adjusts `mask` for feathering and
+/// returns the pixels to composite through it.
+pub fn prepare_region(
+    generated: &RgbaImage,
+    target: &RgbaImage,
+    mask: &mut GrayImage,
+    options: &RegionBlendOptions,
+) -> RgbaImage {
+    let overlay = match options.mode {
+        RegionBlendMode::Poisson => poisson_blend(generated, target, mask),
+        RegionBlendMode::Paste | RegionBlendMode::Feather => generated.clone(),
+    };
+    if options.mode != RegionBlendMode::Paste {
+        feather_mask(mask, options.feather_width);
+    }
+    overlay
+}
+
+/// Composites `generated` into a tile image wherever the region mask is set.
+/// Returns false when the region does not touch the tile.
+pub fn blend_region_into_tile(
+    base: &mut RgbaImage,
+    tile_x: u32,
+    tile_y: u32,
+    generated: &RgbaImage,
+    mask: &GrayImage,
+    region_x: u32,
+    region_y: u32,
+) -> bool {
+    let (tile_w, tile_h) = base.dimensions();
+    let (region_w, region_h) = generated.dimensions();
+    let x1 = tile_x.max(region_x);
+    let y1 = tile_y.max(region_y);
+    let x2 = (tile_x + tile_w).min(region_x + region_w);
+    let y2 = (tile_y + tile_h).min(region_y + region_h);
+    if x1 >= x2 || y1 >= y2 {
+        return false;
+    }
+
+    for gy in y1..y2 {
+        for gx in x1..x2 {
+            let weight = mask.get_pixel(gx - region_x, gy - region_y)[0];
+            if weight == 0 {
+                continue;
+            }
+            let src = generated.get_pixel(gx - region_x, gy - region_y);
+            let dst = base.get_pixel_mut(gx - tile_x, gy - tile_y);
+            *dst = lerp_premultiplied(dst, src, weight as f32 / 255.0);
+        }
+    }
+    true
+}
+
+/// Loads a tile at its expected size, falling back to the original crop or an
+/// empty tile when no result exists yet.
+pub fn load_tile_for_blend(
+    path: &str,
+    fallback_path: Option<&str>,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, String> {
+    let source = [Some(path), fallback_path]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .find(|p| !p.is_empty() && Path::new(p).is_file());
+    match source {
+        Some(source) => {
+            let img = image::open(source).map_err(|e| e.to_string())?.to_rgba8();
+            Ok(resize_rgba(&img, width, height, ResizeFilterType::Lanczos3))
+        }
+        None => Ok(RgbaImage::new(width, height)),
+    }
+}
+
+/// Copies the part of the region covered by each `(x, y, image)` tile out of the
+/// tile images. Pixels no tile covers keep their value from `fallback`.
+fn assemble_region_target(
+    bases: &[(u32, u32, &RgbaImage)],
+    fallback: &RgbaImage,
+    region_x: u32,
+    region_y: u32,
+) -> RgbaImage {
+    let mut target = fallback.clone();
+    let (region_w, region_h) = target.dimensions();
+    for &(tile_x, tile_y, base) in bases {
+        let x1 = tile_x.max(region_x);
+        let y1 = tile_y.max(region_y);
+        let x2 = (tile_x + base.width()).min(region_x + region_w);
+        let y2 = (tile_y + base.height()).min(region_y + region_h);
+        for gy in y1..y2 {
+            for gx in x1..x2 {
+                let px = *base.get_pixel(gx - tile_x, gy - tile_y);
+                target.put_pixel(gx - region_x, gy - region_y, px);
+            }
+        }
+    }
+    target
+}
+
+/// Single-tile variant of `blend_region_into_tiles`. Poisson boundaries outside
+/// the tile fall back to the generated pixels.
+pub fn blend_region_with_options(
+    base: &mut RgbaImage,
+    tile_x: u32,
+    tile_y: u32,
+    generated: &RgbaImage,
+    mut mask: GrayImage,
+    region_x: u32,
+    region_y: u32,
+    options: &RegionBlendOptions,
+) -> bool {
+    let target = assemble_region_target(&[(tile_x, tile_y, &*base)], generated, region_x, region_y);
+    let overlay = prepare_region(generated, &target, &mut mask, options);
+    blend_region_into_tile(base, tile_x, tile_y, &overlay, &mask, region_x, region_y)
+}
+
+/// Blends a generated region into every tile file it overlaps and returns the
+/// `(r, c)` keys of the tiles that were rewritten.
+pub fn blend_region_into_tiles(
+    tiles: &[TileInfo],
+    generated: &RgbaImage,
+    mut mask: GrayImage,
+    region_x: u32,
+    region_y: u32,
+    options: &RegionBlendOptions,
+) -> Result<Vec<(u32, u32)>, String> {
+    let (region_w, region_h) = generated.dimensions();
+    let bases = tiles
+        .par_iter()
+        .filter(|t| {
+            t.width > 0
+                && t.height > 0
+                && t.x < region_x + region_w
+                && region_x < t.x + t.width
+                && t.y < region_y + region_h
+                && region_y < t.y + t.height
+        })
+        .map(|t| {
+            let base = load_tile_for_blend(&t.path, Some(&t.original_path), t.width, t.height)?;
+            Ok((t, base))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let views: Vec<_> = bases.iter().map(|(t, base)| (t.x, t.y, base)).collect();
+    let target = assemble_region_target(&views, generated, region_x, region_y);
+    let overlay = prepare_region(generated, &target, &mut mask, options);
+
+    bases
+        .into_par_iter()
+        .map(|(t, mut base)| {
+            blend_region_into_tile(&mut base, t.x, t.y, &overlay, &mask, region_x, region_y);
+            save_rgba_image_auto(&t.path, &base)?;
+            Ok((t.r, t.c))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_polygon_mask_composites_only_inside_shape() {
+        // Right triangle covering the lower-left half of an 8x8 region at (10, 20).
+        let points = vec![(10.0, 20.0), (10.0, 28.0), (18.0, 28.0)];
+        let shape = RegionShape::Polygon { points };
+        let mask = region_mask(&shape, 10, 20, 8, 8).unwrap();
+        assert_eq!(mask.get_pixel(1, 6)[0], 255);
+        assert_eq!(mask.get_pixel(6, 1)[0], 0);
+        assert!(mask.get_pixel(3, 3)[0] > 64 && mask.get_pixel(3, 3)[0] < 192);
+
+        let generated = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
+        let mut tile = RgbaImage::from_pixel(6, 6, Rgba([0, 0, 255, 255]));
+        // Tile at (14, 24) covers the region's bottom-right quarter and beyond.
+        assert!(blend_region_into_tile(
+            &mut tile, 14, 24, &generated, &mask, 10, 20
+        ));
+        assert_eq!(*tile.get_pixel(0, 3), Rgba([255, 0, 0, 255]));
+        assert_eq!(*tile.get_pixel(3, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*tile.get_pixel(5, 5), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_poisson_blend_matches_border_and_keeps_detail() {
+        let target = RgbaImage::from_pixel(24, 24, Rgba([100, 100, 100, 255]));
+        // Overlay is 60 levels brighter with a single bright detail pixel.
+        let mut generated = RgbaImage::from_pixel(24, 24, Rgba([160, 160, 160, 255]));
+        generated.put_pixel(12, 12, Rgba([220, 160, 160, 255]));
+        let mask = GrayImage::from_pixel(24, 24, Luma([255]));
+
+        let out = poisson_blend(&generated, &target, &mask);
+        assert!(out.get_pixel(1, 12)[0].abs_diff(100) <= 2);
+        assert!(out.get_pixel(11, 12)[0].abs_diff(100) <= 2);
+        let detail = out.get_pixel(12, 12)[0] as i32 - out.get_pixel(11, 12)[0] as i32;
+        assert!((detail - 60).abs() <= 3);
+
+        let mut feathered = GrayImage::from_pixel(9, 9, Luma([255]));
+        feather_mask(&mut feathered, 4);
+        assert!(feathered.get_pixel(0, 4)[0] < feathered.get_pixel(4, 4)[0]);
+        assert_eq!(feathered.get_pixel(4, 4)[0], 255);
+    }
+}
@@ -0,0 +1,116 @@
+use image::imageops::FilterType as ResizeFilterType;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+
+pub const REGION_MASK_FILE: &str = "region_mask.png";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskIntent {
+    Auto,
+    /// Always take the pixel from the original source.
+    Protect,
+    /// Always take the pixel from the AI output.
+    Force,
+}
+
+/// Full-resolution user mask stored in the session directory.
+///
+/// Painted masks are RGBA: transparent pixels leave the merge alone, opaque dark
+/// pixels protect the original and opaque light pixels force the AI output.
+pub struct RegionMask {
+    width: u32,
+    height: u32,
+    intents: Vec<MaskIntent>,
+}
+
+impl RegionMask {
+    pub fn from_rgba(image: &RgbaImage) -> Self {
+        let intents = image.pixels().map(intent_from_pixel).collect();
+        Self {
+            width: image.width(),
+            height: image.height(),
+            intents,
+        }
+    }
+
+    /// Loads the session mask, rescaling it to `width` x `height` if needed.
+    pub fn load(path: &Path, width: u32, height: u32) -> Result<Self, String> {
+        let mut image = image::open(path)
+            .map_err(|e| format!("Failed to open region mask {}: {}", path.display(), e))?
+            .to_rgba8();
+        if image.width() != width || image.height() != height {
+            image = DynamicImage::ImageRgba8(image)
+                .resize_exact(width, height, ResizeFilterType::Nearest)
+                .to_rgba8();
+        }
+        Ok(Self::from_rgba(&image))
+    }
+
+    /// Canonical black/white/transparent rendering used when persisting the mask.
+    pub fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| match self.intent(x, y) {
+            MaskIntent::Auto => Rgba([0, 0, 0, 0]),
+            MaskIntent::Protect => Rgba([0, 0, 0, 255]),
+            MaskIntent::Force => Rgba([255, 255, 255, 255]),
+        })
+    }
+
+    pub fn intent(&self, x: u32, y: u32) -> MaskIntent {
+        if x >= self.width || y >= self.height {
+            return MaskIntent::Auto;
+        }
+        self.intents[(y * self.width + x) as usize]
+    }
+
+    /// True when every pixel of the rectangle must come from the original.
+    pub fn fully_protected(&self, x: u32, y: u32, width: u32, height: u32) -> bool {
+        if width == 0 || height == 0 {
+            return false;
+        }
+        let x2 = x.saturating_add(width);
+        let y2 = y.saturating_add(height);
+        if x2 > self.width || y2 > self.height {
+            return false;
+        }
+        (y..y2).all(|py| (x..x2).all(|px| self.intent(px, py) == MaskIntent::Protect))
+    }
+
+    pub fn has_intent_in(&self, intent: MaskIntent, x: u32, y: u32, width: u32, height: u32) -> bool {
+        let x2 = x.saturating_add(width).min(self.width);
+        let y2 = y.saturating_add(height).min(self.height);
+        (y..y2).any(|py| (x..x2).any(|px| self.intent(px, py) == intent))
+    }
+}
+
+fn intent_from_pixel(p: &Rgba<u8>) -> MaskIntent {
+    if p[3] < 128 {
+        return MaskIntent::Auto;
+    }
+    let luma = (p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000;
+    if luma < 128 {
+        MaskIntent::Protect
+    } else {
+        MaskIntent::Force
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_mask_intents_and_protected_tiles() {
+        let painted = RgbaImage::from_fn(8, 4, |x, _| match x {
+            0..=3 => Rgba([10, 10, 10, 255]),
+            4 => Rgba([250, 250, 250, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let mask = RegionMask::from_rgba(&painted);
+
+        assert_eq!(mask.intent(1, 1), MaskIntent::Protect);
+        assert_eq!(mask.intent(4, 1), MaskIntent::Force);
+        assert_eq!(mask.intent(6, 1), MaskIntent::Auto);
+        assert!(mask.fully_protected(0, 0, 4, 4));
+        assert!(!mask.fully_protected(2, 0, 4, 4));
+    }
+}
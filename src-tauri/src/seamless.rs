@@ -0,0 +1,292 @@
+use crate::image_processing::{
+    prepare_split_source, write_tile_files, MergeContext, MergeOptions, TileInfo,
+};
+use crate::region_blend::load_tile_for_blend;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// Tile spans along one wrapped axis. Each span runs from its start to the next
+/// start plus `overlap`, so the last span crosses the edge into the first.
+struct WrapAxis {
+    size: u32,
+    starts: Vec<u32>,
+    overlap: u32,
+}
+
+impl WrapAxis {
+    fn new(size: u32, count: u32, overlap_ratio: f64) -> Self {
+        let count = count.clamp(1, size.max(1));
+        let starts: Vec<u32> = (0..count)
+            .map(|k| (k as u64 * size as u64 / count as u64) as u32)
+            .collect();
+        let min_span = (0..count as usize)
+            .map(|k| starts.get(k + 1).copied().unwrap_or(size) - starts[k])
+            .min()
+            .unwrap_or(size);
+        // Keep ramps from meeting inside a tile so the weights stay a partition of unity.
+        let overlap = ((size as f64 / count as f64) * overlap_ratio.clamp(0.0, 0.5)).round() as u32;
+        Self {
+            size,
+            overlap: overlap.min(min_span / 2),
+            starts,
+        }
+    }
+
+    fn span(&self, k: usize) -> (u32, u32) {
+        let end = self.starts.get(k + 1).copied().unwrap_or(self.size);
+        (self.starts[k], end - self.starts[k] + self.overlap)
+    }
+
+    /// `(span, offset, weight)` of every span covering each pixel of the axis.
+    fn coverage(&self) -> Vec<Vec<(usize, u32, f32)>> {
+        let mut table = vec![Vec::new(); self.size as usize];
+        for k in 0..self.starts.len() {
+            let (start, len) = self.span(k);
+            for pos in 0..len {
+                let weight = self.weight(pos, len);
+                if weight > 0.0 {
+                    table[((start + pos) % self.size) as usize].push((k, pos, weight));
+                }
+            }
+        }
+        table
+    }
+
+    /// Linear cross-fade weight at offset `pos` of a span of length `len`.
+    fn weight(&self, pos: u32, len: u32) -> f32 {
+        if self.overlap == 0 {
+            return 1.0;
+        }
+        let ov = self.overlap as f32;
+        let head = (pos as f32 + 0.5) / ov;
+        let tail = (len as f32 - pos as f32 - 0.5) / ov;
+        head.min(tail).min(1.0)
+    }
+}
+
+/// Copy of `image` extended by `pad_w` x `pad_h` pixels taken from the opposite edges.
+fn pad_periodic(image: &RgbaImage, pad_w: u32, pad_h: u32) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    RgbaImage::from_fn(w + pad_w, h + pad_h, |x, y| *image.get_pixel(x % w, y % h))
+}
+
+/// Splits the input into a wrap-around grid for tileable textures.
+///
+/// The last column overlaps the first and the last row overlaps the first. Tile
+/// `x`/`y` are in unwrapped coordinates and may extend past the image size.
+pub fn split_image_wrap(
+    input_path: &str,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<(Vec<TileInfo>, u32, u32, String), String> {
+    if rows == 0 || cols == 0 {
+        return Err("Rows and cols must be greater than zero".to_string());
+    }
+
+    let (img_rgba, image_format, new_input_path) =
+        prepare_split_source(input_path, prefer_jpeg, output_dir)?;
+    let (w, h) = img_rgba.dimensions();
+    let axis_x = WrapAxis::new(w, cols, overlap_ratio_x);
+    let axis_y = WrapAxis::new(h, rows, overlap_ratio_y);
+    let padded = pad_periodic(&img_rgba, axis_x.overlap, axis_y.overlap);
+
+    let mut tile_configs = Vec::new();
+    for r in 0..axis_y.starts.len() {
+        let (y, th) = axis_y.span(r);
+        for c in 0..axis_x.starts.len() {
+            let (x, tw) = axis_x.span(c);
+            tile_configs.push((r as u32, c as u32, x, y, tw, th));
+        }
+    }
+
+    let tiles = write_tile_files(&padded, tile_configs, image_format, output_dir, "")?;
+    Ok((tiles, w, h, new_input_path))
+}
+
+/// Merges wrap-around tiles, cross-fading every overlap cyclically so the result
+/// tiles seamlessly in both directions. Keying follows `options` as in `merge_tiles`.
+pub fn merge_wrap_tiles(
+    tiles: Vec<TileInfo>,
+    width: u32,
+    height: u32,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    key_color: &str,
+    remove_bg: bool,
+    tolerance: u8,
+    options: &MergeOptions,
+) -> Result<String, String> {
+    if tiles.is_empty() {
+        return Err("No tiles to merge".to_string());
+    }
+    if width == 0 || height == 0 {
+        return Err("Image dimensions must be greater than zero".to_string());
+    }
+    // Dual-background tiles already carry their alpha.
+    let remove_bg = remove_bg && !options.dual_background;
+    let axis_x = WrapAxis::new(width, cols, overlap_ratio_x);
+    let axis_y = WrapAxis::new(height, rows, overlap_ratio_y);
+    let ctx = MergeContext::new(
+        &tiles[0].path,
+        width,
+        height,
+        key_color,
+        remove_bg,
+        tolerance,
+        options,
+    )?;
+
+    let grid_cols = axis_x.starts.len();
+    let loaded: Vec<(usize, RgbaImage)> = tiles
+        .into_par_iter()
+        .filter(|tile| (tile.r as usize) < axis_y.starts.len() && (tile.c as usize) < grid_cols)
+        .map(|tile| {
+            let (r, c) = (tile.r as usize, tile.c as usize);
+            let (_, span_w) = axis_x.span(c);
+            let (_, span_h) = axis_y.span(r);
+            let img = load_tile_for_blend(&tile.path, Some(&tile.original_path), span_w, span_h)?;
+            Ok((r * grid_cols + c, img))
+        })
+        .collect::<Result<_, String>>()?;
+    let mut images: Vec<Option<RgbaImage>> = vec![None; axis_y.starts.len() * grid_cols];
+    for (idx, img) in loaded {
+        images[idx] = Some(img);
+    }
+
+    // Each output pixel gathers from the few tiles covering it, so no full-frame
+    // accumulators are needed.
+    let columns = axis_x.coverage();
+    let rows_cover = axis_y.coverage();
+    let mut merged = RgbaImage::new(width, height);
+    merged
+        .as_flat_samples_mut()
+        .as_mut_slice()
+        .par_chunks_exact_mut(width as usize * 4)
+        .zip(rows_cover.par_iter())
+        .for_each(|(row, row_cover)| {
+            for (pixel, column_cover) in row.chunks_exact_mut(4).zip(columns.iter()) {
+                let mut sum = [0.0f32; 4];
+                let mut total = 0.0f32;
+                for &(r, ly, wy) in row_cover {
+                    for &(c, lx, wx) in column_cover {
+                        let Some(img) = images[r * grid_cols + c].as_ref() else {
+                            continue;
+                        };
+                        let px = img.get_pixel(lx, ly);
+                        let weight = wx * wy;
+                        // Colour is accumulated premultiplied so transparent tiles add no
+                        // dark fringe.
+                        let alpha = px[3] as f32 * weight;
+                        for ch in 0..3 {
+                            sum[ch] += px[ch] as f32 * alpha;
+                        }
+                        sum[3] += alpha;
+                        total += weight;
+                    }
+                }
+                if total <= 0.0 {
+                    continue;
+                }
+                if sum[3] > 0.0 {
+                    for ch in 0..3 {
+                        pixel[ch] = (sum[ch] / sum[3]).round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+                pixel[3] = (sum[3] / total).round().clamp(0.0, 255.0) as u8;
+            }
+        });
+
+    ctx.finish(merged, None)
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeMismatch {
+    /// Mean channel difference (0-255) between the last and first column.
+    pub horizontal: f64,
+    /// Mean channel difference (0-255) between the last and first row.
+    pub vertical: f64,
+    /// Mean difference between neighbouring pixels inside the image.
+    pub interior: f64,
+    /// Worst wrap edge relative to the interior; around 1.0 means the seam is invisible.
+    pub score: f64,
+}
+
+fn pixel_diff(a: &Rgba<u8>, b: &Rgba<u8>) -> f64 {
+    (0..4).map(|ch| a[ch].abs_diff(b[ch]) as f64).sum::<f64>() / 4.0
+}
+
+/// Measures how visible the wrap-around edges are when the image is tiled.
+pub fn edge_mismatch(image: &RgbaImage) -> EdgeMismatch {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return EdgeMismatch {
+            horizontal: 0.0,
+            vertical: 0.0,
+            interior: 0.0,
+            score: 0.0,
+        };
+    }
+
+    let horizontal = (0..h)
+        .map(|y| pixel_diff(image.get_pixel(w - 1, y), image.get_pixel(0, y)))
+        .sum::<f64>()
+        / h as f64;
+    let vertical = (0..w)
+        .map(|x| pixel_diff(image.get_pixel(x, h - 1), image.get_pixel(x, 0)))
+        .sum::<f64>()
+        / w as f64;
+
+    let mut interior_sum = 0.0;
+    let mut interior_count = 0u64;
+    for (x, y, px) in image.enumerate_pixels() {
+        if x + 1 < w {
+            interior_sum += pixel_diff(px, image.get_pixel(x + 1, y));
+            interior_count += 1;
+        }
+        if y + 1 < h {
+            interior_sum += pixel_diff(px, image.get_pixel(x, y + 1));
+            interior_count += 1;
+        }
+    }
+    let interior = if interior_count > 0 {
+        interior_sum / interior_count as f64
+    } else {
+        0.0
+    };
+
+    EdgeMismatch {
+        horizontal,
+        vertical,
+        interior,
+        score: horizontal.max(vertical) / interior.max(1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_weights_cover_every_pixel_once() {
+        let axis = WrapAxis::new(100, 3, 0.2);
+        let coverage = axis.coverage();
+        assert_eq!(coverage.len(), 100);
+        assert!(coverage.iter().all(|spans| {
+            let total: f32 = spans.iter().map(|&(_, _, weight)| weight).sum();
+            spans.len() <= 2 && (total - 1.0).abs() < 1e-4
+        }));
+
+        let ramp = RgbaImage::from_fn(16, 4, |x, _| Rgba([(x * 16) as u8, 0, 0, 255]));
+        let flat = RgbaImage::from_pixel(16, 4, Rgba([90, 90, 90, 255]));
+        assert!(edge_mismatch(&ramp).score > 10.0);
+        assert_eq!(edge_mismatch(&flat).score, 0.0);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::filters::{lerp_premultiplied, smoothstep};
+use crate::image_processing::{
+    encode_jpeg_data_url_fast, encode_png_data_url_fast, write_tile_files, ImageFileFormat,
+    TileInfo,
+};
+use crate::region_blend::load_tile_for_blend;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// A first-pass seam: the centre line of the overlap between two neighbouring tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Seam {
+    /// Side-by-side tiles meet along a vertical seam, stacked tiles along a horizontal one.
+    vertical: bool,
+    /// Centre of the overlap across the seam.
+    pos: u32,
+    /// Extent `start..end` along the seam.
+    start: u32,
+    end: u32,
+    /// Size of the larger of the two tiles, used for the seam tile.
+    tile_w: u32,
+    tile_h: u32,
+}
+
+impl Seam {
+    fn covers(&self, other: &Seam) -> bool {
+        self.vertical == other.vertical
+            && self.pos == other.pos
+            && self.start <= other.start
+            && other.end <= self.end
+    }
+}
+
+/// Seams between every pair of overlapping first-pass tile rects `(x, y, width, height)`,
+/// so grids, brick layouts and planned tiles all get their seams where the tiles meet.
+fn find_seams(rects: &[(u32, u32, u32, u32)]) -> Vec<Seam> {
+    let mut candidates = Vec::new();
+    for (i, &(ax, ay, aw, ah)) in rects.iter().enumerate() {
+        for &(bx, by, bw, bh) in &rects[i + 1..] {
+            let (x0, x1) = (ax.max(bx), (ax + aw).min(bx + bw));
+            let (y0, y1) = (ay.max(by), (ay + ah).min(by + bh));
+            if x1 <= x0 || y1 <= y0 {
+                continue;
+            }
+            // The seam runs along the long side of the overlap. Diagonal neighbours only
+            // share a corner, which the seams of the side-by-side pairs already cover.
+            let (vertical, pos, start, end) = if y1 - y0 > x1 - x0 && ax != bx {
+                (true, (x0 + x1) / 2, y0, y1)
+            } else if x1 - x0 > y1 - y0 && ay != by {
+                (false, (y0 + y1) / 2, x0, x1)
+            } else {
+                continue;
+            };
+            candidates.push(Seam {
+                vertical,
+                pos,
+                start,
+                end,
+                tile_w: aw.max(bw),
+                tile_h: ah.max(bh),
+            });
+        }
+    }
+
+    let mut seams: Vec<Seam> = Vec::with_capacity(candidates.len());
+    for (i, seam) in candidates.iter().enumerate() {
+        let covered = candidates
+            .iter()
+            .enumerate()
+            .any(|(j, other)| j != i && other.covers(seam) && (!seam.covers(other) || j < i));
+        if !covered {
+            seams.push(*seam);
+        }
+    }
+    seams
+}
+
+/// Rectangle `(x, y, width, height)` of the seam tile centred on `seam`, clamped to the image.
+fn seam_tile_rect(seam: &Seam, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let (tile_w, tile_h) = (seam.tile_w.min(width), seam.tile_h.min(height));
+    let middle = seam.start + (seam.end - seam.start) / 2;
+    let (cx, cy) = if seam.vertical {
+        (seam.pos, middle)
+    } else {
+        (middle, seam.pos)
+    };
+    let x = cx.saturating_sub(tile_w / 2).min(width - tile_w);
+    let y = cy.saturating_sub(tile_h / 2).min(height - tile_h);
+    (x, y, tile_w, tile_h)
+}
+
+/// Splits a first-pass merged image into tiles centred on the seams between `rects`,
+/// the tile rectangles the first pass was generated from.
+///
+/// Seam tile `i` is saved as `orig_seam_tile_{i}_0` and its result is expected at
+/// `seam_tile_{i}_0`.
+pub fn split_seam_tiles(
+    base_path: &str,
+    rects: &[(u32, u32, u32, u32)],
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<Vec<TileInfo>, String> {
+    let base = image::open(base_path)
+        .map_err(|e| format!("Failed to open {}: {}", base_path, e))?
+        .to_rgba8();
+    let (w, h) = base.dimensions();
+    let seams = find_seams(rects);
+    if seams.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let image_format = if prefer_jpeg {
+        ImageFileFormat::Jpeg
+    } else {
+        ImageFileFormat::Png
+    };
+    let tile_configs = seams
+        .iter()
+        .enumerate()
+        .map(|(i, seam)| {
+            let (x, y, tile_w, tile_h) = seam_tile_rect(seam, w, h);
+            (i as u32, 0, x, y, tile_w, tile_h)
+        })
+        .collect();
+
+    write_tile_files(&base, tile_configs, image_format, output_dir, "seam_")
+}
+
+/// Weight of the seam tiles by distance from the seam centre line, `half_band + 1` entries
+/// falling from 1 at the seam to 0 at the band edge.
+fn band_profile(half_band: u32) -> Vec<f32> {
+    let half = half_band as f32;
+    (0..=half_band)
+        .map(|d| 1.0 - smoothstep(half * 0.5, half, d as f32))
+        .collect()
+}
+
+/// Fade-in of a band over the first `half_band` pixels of an interior seam end, so a seam
+/// that stops inside the image does not leave a hard edge.
+fn end_taper(half_band: u32) -> Vec<f32> {
+    let half = half_band as f32;
+    (0..half_band)
+        .map(|e| smoothstep(0.0, half, e as f32 + 0.5))
+        .collect()
+}
+
+/// Composites regenerated seam tiles back into the first-pass merge.
+///
+/// Only pixels within `band_width / 2` of a seam between `rects` are touched. Inside the
+/// band the seam tiles fade out towards the band edge, and overlapping seam tiles are
+/// averaged with weights that fall off towards their own borders. Only colour is
+/// blended: the alpha the first-pass merge keyed stays as it is.
+pub fn merge_seam_tiles(
+    base_path: &str,
+    tiles: Vec<TileInfo>,
+    rects: &[(u32, u32, u32, u32)],
+    band_width: u32,
+    remove_bg: bool,
+) -> Result<String, String> {
+    let mut base = image::open(base_path)
+        .map_err(|e| format!("Failed to open {}: {}", base_path, e))?
+        .to_rgba8();
+    let (w, h) = base.dimensions();
+    let seams = find_seams(rects);
+    let half_band = band_width.max(2) / 2;
+    let profile = band_profile(half_band);
+    let taper = end_taper(half_band);
+
+    let loaded: Vec<(TileInfo, RgbaImage)> = tiles
+        .into_par_iter()
+        .filter(|tile| tile.width > 0 && tile.height > 0)
+        .map(|tile| {
+            let img = load_tile_for_blend(
+                &tile.path,
+                Some(&tile.original_path),
+                tile.width,
+                tile.height,
+            )?;
+            Ok((tile, img))
+        })
+        .collect::<Result<_, String>>()?;
+
+    for seam in &seams {
+        // Band rectangle in image coordinates; `u` runs across the seam, `v` along it.
+        let (u0, u1) = (
+            seam.pos.saturating_sub(half_band),
+            (seam.pos + half_band + 1).min(if seam.vertical { w } else { h }),
+        );
+        let (v0, v1) = (seam.start, seam.end.min(if seam.vertical { h } else { w }));
+        if u1 <= u0 || v1 <= v0 {
+            continue;
+        }
+        let (band_x, band_y, band_w, band_h) = if seam.vertical {
+            (u0, v0, u1 - u0, v1 - v0)
+        } else {
+            (v0, u0, v1 - v0, u1 - u0)
+        };
+
+        // Weighted premultiplied sums over the band only, divided once so overlapping
+        // tiles do not depend on their order or lose precision.
+        let mut sums = vec![[0.0f32; 4]; (band_w * band_h) as usize];
+        let mut weights = vec![0.0f32; (band_w * band_h) as usize];
+        for (tile, img) in &loaded {
+            let x0 = tile.x.max(band_x);
+            let y0 = tile.y.max(band_y);
+            let x1 = (tile.x + tile.width).min(band_x + band_w);
+            let y1 = (tile.y + tile.height).min(band_y + band_h);
+            for gy in y0..y1 {
+                let ly = gy - tile.y;
+                let edge_y = ly.min(tile.height - 1 - ly);
+                for gx in x0..x1 {
+                    let lx = gx - tile.x;
+                    let tile_weight = lx.min(tile.width - 1 - lx).min(edge_y) as f32 + 1.0;
+                    let px = img.get_pixel(lx, ly);
+                    let idx = ((gy - band_y) * band_w + (gx - band_x)) as usize;
+                    let alpha = px[3] as f32 * tile_weight;
+                    let sum = &mut sums[idx];
+                    for ch in 0..3 {
+                        sum[ch] += px[ch] as f32 * alpha;
+                    }
+                    sum[3] += alpha;
+                    weights[idx] += tile_weight;
+                }
+            }
+        }
+
+        let along_limit = if seam.vertical { h } else { w };
+        for by in 0..band_h {
+            for bx in 0..band_w {
+                let idx = (by * band_w + bx) as usize;
+                if weights[idx] <= 0.0 || sums[idx][3] <= 0.0 {
+                    continue;
+                }
+                let (gx, gy) = (band_x + bx, band_y + by);
+                let (u, v) = if seam.vertical { (gx, gy) } else { (gy, gx) };
+                let mut factor = profile[u.abs_diff(seam.pos) as usize];
+                if v0 > 0 {
+                    factor *= taper.get((v - v0) as usize).copied().unwrap_or(1.0);
+                }
+                if v1 < along_limit {
+                    factor *= taper.get((v1 - 1 - v) as usize).copied().unwrap_or(1.0);
+                }
+                let px = base.get_pixel_mut(gx, gy);
+                if factor <= 0.0 || px[3] == 0 {
+                    continue;
+                }
+                let mut seam_px = unpremultiply(sums[idx], weights[idx]);
+                seam_px[3] = px[3];
+                *px = lerp_premultiplied(px, &seam_px, factor);
+            }
+        }
+    }
+
+    if remove_bg {
+        encode_png_data_url_fast(&base)
+    } else {
+        encode_jpeg_data_url_fast(&base, 90)
+    }
+}
+
+/// Pixel from weighted premultiplied sums: colour channels are summed as
+/// `colour * alpha * weight` and `sum[3]` as `alpha * weight`.
+fn unpremultiply(sum: [f32; 4], weight: f32) -> Rgba<u8> {
+    if sum[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |ch: usize| (sum[ch] / sum[3]).round().clamp(0.0, 255.0) as u8;
+    let alpha = (sum[3] / weight).round().clamp(0.0, 255.0) as u8;
+    Rgba([channel(0), channel(1), channel(2), alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_processing::{GridGeometry, TileLayout};
+
+    fn layout_rects(grid: &GridGeometry) -> Vec<(u32, u32, u32, u32)> {
+        grid.tile_keys()
+            .into_iter()
+            .filter_map(|(r, c)| grid.tile_rect(r, c))
+            .collect()
+    }
+
+    #[test]
+    fn test_grid_seams_are_centred_on_overlaps() {
+        let grid = GridGeometry::new(300, 200, 2, 3, 0.2, 0.2).unwrap();
+        let seams = find_seams(&layout_rects(&grid));
+
+        // Two vertical seams per row, one horizontal seam per column; corners add none.
+        let vertical: Vec<_> = seams.iter().filter(|s| s.vertical).collect();
+        assert_eq!(vertical.len(), 4);
+        assert_eq!(seams.len() - vertical.len(), 3);
+        for seam in &vertical {
+            assert_eq!(seam.pos % grid.stride_w, grid.overlap_w / 2);
+        }
+        for seam in seams.iter().filter(|s| !s.vertical) {
+            assert_eq!(seam.pos, grid.stride_h + grid.overlap_h / 2);
+        }
+    }
+
+    #[test]
+    fn test_brick_seams_follow_shifted_rows() {
+        let grid = GridGeometry::new(300, 200, 2, 3, 0.2, 0.2)
+            .unwrap()
+            .with_layout(TileLayout::Brick);
+        let rects = layout_rects(&grid);
+        let seams = find_seams(&rects);
+        let (w, h) = (grid.width, grid.height);
+
+        let row_seams = |y: u32| -> Vec<u32> {
+            let mut xs: Vec<u32> = seams
+                .iter()
+                .filter(|s| s.vertical && s.start <= y && y < s.end)
+                .map(|s| s.pos)
+                .collect();
+            xs.sort_unstable();
+            xs
+        };
+        let top = row_seams(0);
+        let bottom = row_seams(h - 1);
+        assert_eq!(top.len(), 2);
+        assert_eq!(bottom.len(), 3);
+        // Shifted rows meet half a stride away from the unshifted ones.
+        for (x_top, x_bottom) in top.iter().zip(bottom.iter().skip(1)) {
+            assert_eq!(x_bottom - x_top, grid.stride_w - grid.stride_w / 2);
+        }
+
+        let horizontal: Vec<_> = seams.iter().filter(|s| !s.vertical).collect();
+        assert!(!horizontal.is_empty());
+        assert!(horizontal
+            .iter()
+            .all(|s| s.pos == grid.stride_h + grid.overlap_h / 2));
+        let mut covered = vec![false; w as usize];
+        for seam in &horizontal {
+            covered[seam.start as usize..seam.end as usize].fill(true);
+        }
+        assert!(covered.iter().all(|&c| c));
+
+        // Every seam lies inside the tile cut for it.
+        for seam in &seams {
+            let (x, y, tile_w, tile_h) = seam_tile_rect(seam, w, h);
+            let (u, v) = if seam.vertical { (x, y) } else { (y, x) };
+            let (across, along) = if seam.vertical {
+                (tile_w, tile_h)
+            } else {
+                (tile_h, tile_w)
+            };
+            assert!(u < seam.pos && seam.pos < u + across);
+            assert!(v <= seam.start && seam.end <= v + along);
+        }
+    }
+
+    #[test]
+    fn test_band_weights_fall_off_from_seam_and_interior_ends() {
+        let profile = band_profile(8);
+        assert_eq!(profile.len(), 9);
+        assert_eq!(profile[0], 1.0);
+        assert_eq!(profile[8], 0.0);
+        assert!(profile.windows(2).all(|w| w[0] >= w[1]));
+
+        let taper = end_taper(8);
+        assert_eq!(taper.len(), 8);
+        assert!(taper[0] > 0.0 && taper[7] < 1.0);
+        assert!(taper.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_unpremultiply_weights_colour_by_coverage() {
+        // Half-covered red over opaque blue, weighted 3:1: colour follows coverage.
+        let mut sum = [0.0f32; 4];
+        for (px, weight) in [
+            ([255.0, 0.0, 0.0, 128.0], 3.0),
+            ([0.0, 0.0, 255.0, 255.0], 1.0),
+        ] {
+            for ch in 0..3 {
+                sum[ch] += px[ch] * px[3] * weight;
+            }
+            sum[3] += px[3] * weight;
+        }
+        assert_eq!(unpremultiply(sum, 4.0), Rgba([153, 0, 102, 160]));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::flood_fill::scanline_fill;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// Largest opacity difference from where a flat neutral area meets the backdrop.
+const FLAT_TOLERANCE: u8 = 12;
+
+/// Shadow layer written next to the session tiles by a shadow-preserving merge.
+pub const SHADOW_LAYER_FILE: &str = "shadow_layer.png";
+
+/// Keeps cast shadows on a removed backdrop as semi-transparent black.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShadowOptions {
+    /// Darkening below this fraction of the backdrop luminance is keyed as usual.
+    pub threshold: f32,
+    /// Scales shadow opacity; 1.0 reproduces the original darkening over the backdrop.
+    pub strength: f32,
+    /// Darkest shadow kept; anything darker is treated as subject.
+    pub max_opacity: f32,
+    /// Largest per-channel deviation (0-255) from a uniformly darkened backdrop.
+    pub chroma_tolerance: f32,
+    /// Neutral areas that meet removed background with an opacity jump above this
+    /// are subject, such as a grey product, rather than a shadow fading out.
+    pub edge_step: f32,
+}
+
+impl Default for ShadowOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.04,
+            strength: 1.0,
+            max_opacity: 0.85,
+            chroma_tolerance: 16.0,
+            edge_step: 0.25,
+        }
+    }
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+    0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2]
+}
+
+/// Shadow opacity (0-255) when `p` looks like `background` darkened without a hue
+/// change, `None` otherwise.
+pub fn shadow_alpha(p: &Rgba<u8>, background: [u8; 3], options: &ShadowOptions) -> Option<u8> {
+    if p[3] < 10 {
+        return None;
+    }
+    let bg = background.map(|v| v as f32);
+    let bg_luma = luma(bg);
+    if bg_luma < 1.0 {
+        return None;
+    }
+    let ratio = luma([p[0] as f32, p[1] as f32, p[2] as f32]) / bg_luma;
+    let darkening = 1.0 - ratio;
+    if darkening < options.threshold || darkening > options.max_opacity {
+        return None;
+    }
+    let neutral =
+        (0..3).all(|ch| (p[ch] as f32 - ratio * bg[ch]).abs() <= options.chroma_tolerance);
+    if !neutral {
+        return None;
+    }
+    let opacity = (darkening * options.strength).clamp(0.0, 1.0);
+    Some((opacity * 255.0).round() as u8).filter(|&alpha| alpha > 0)
+}
+
+/// Subject mask for the shadow pass: `subject` (protected or guarded pixels), every
+/// non-neutral pixel that was kept, and flat neutral areas with a hard edge against
+/// `removed` background. Cast shadows fade into the backdrop, so they stay outside.
+fn subject_mask(
+    shadow: &[u8],
+    removed: &[bool],
+    subject: &[bool],
+    w: usize,
+    h: usize,
+    options: &ShadowOptions,
+) -> Vec<bool> {
+    let mut mask: Vec<bool> = (0..shadow.len())
+        .map(|idx| subject[idx] || (!removed[idx] && shadow[idx] == 0))
+        .collect();
+    let edge = (options.edge_step.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let neighbours = |idx: usize| {
+        let (x, y) = (idx % w, idx / w);
+        [
+            (x > 0).then(|| idx - 1),
+            (x + 1 < w).then(|| idx + 1),
+            (y > 0).then(|| idx - w),
+            (y + 1 < h).then(|| idx + w),
+        ]
+        .into_iter()
+        .flatten()
+    };
+
+    let mut queue: VecDeque<(usize, u8)> = (0..shadow.len())
+        .filter(|&idx| shadow[idx] > edge && neighbours(idx).any(|n| removed[n]))
+        .map(|idx| (idx, shadow[idx]))
+        .collect();
+    for &(idx, _) in &queue {
+        mask[idx] = true;
+    }
+    // Grow within a tolerance of the edge opacity so shadow gradients are not swallowed.
+    while let Some((idx, edge_alpha)) = queue.pop_front() {
+        for n in neighbours(idx) {
+            if !mask[n] && shadow[n] > 0 && shadow[n].abs_diff(edge_alpha) <= FLAT_TOLERANCE {
+                mask[n] = true;
+                queue.push_back((n, edge_alpha));
+            }
+        }
+    }
+    mask
+}
+
+/// Per-pixel shadow opacity for `image`. Shadow pixels darken the backdrop, lie
+/// outside the subject mask and are connected to `removed` background, so dark areas
+/// enclosed by the subject stay. `background` gives the backdrop colour at a pixel
+/// index, or `None` for pixels that must not become shadow; `subject` marks pixels
+/// known to belong to the subject.
+pub fn extract_shadow<F>(
+    image: &RgbaImage,
+    removed: &[bool],
+    subject: &[bool],
+    background: F,
+    options: &ShadowOptions,
+) -> Vec<u8>
+where
+    F: Fn(usize) -> Option<[u8; 3]> + Sync,
+{
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let mut shadow: Vec<u8> = image
+        .as_raw()
+        .par_chunks_exact(4)
+        .enumerate()
+        .map(|(idx, pixel)| {
+            let p = Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            background(idx)
+                .and_then(|bg| shadow_alpha(&p, bg, options))
+                .unwrap_or(0)
+        })
+        .collect();
+    let subject = subject_mask(&shadow, removed, subject, w, h, options);
+    for (alpha, &subject) in shadow.iter_mut().zip(&subject) {
+        if subject {
+            *alpha = 0;
+        }
+    }
+
+    // Keep only shadow reachable from removed background through shadow pixels.
+    let passable: Vec<bool> = removed
+        .iter()
+        .zip(&shadow)
+        .map(|(&removed, &alpha)| removed || alpha > 0)
+        .collect();
+    let mut reached = vec![false; passable.len()];
+    for idx in 0..removed.len() {
+        if removed[idx] && !reached[idx] {
+            scanline_fill(&passable, &mut reached, w, h, idx % w, idx / w);
+        }
+    }
+    for (alpha, &reached) in shadow.iter_mut().zip(&reached) {
+        if !reached {
+            *alpha = 0;
+        }
+    }
+    shadow
+}
+
+/// Black layer carrying the shadow opacity.
+pub fn shadow_layer(shadow: &[u8], width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([0, 0, 0, shadow[(y * width + x) as usize]])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shadow fading out to the right of `x = 15` over rows 8..15, with a soft
+    /// penumbra above and below.
+    fn cast_shadow(x: u32, y: u32) -> Option<Rgba<u8>> {
+        if !(15..35).contains(&x) || !(6..17).contains(&y) {
+            return None;
+        }
+        let core = 140 + (x - 15) * 5;
+        let g = match y {
+            6 | 16 => core + (255 - core) * 2 / 3,
+            7 | 15 => core + (255 - core) / 3,
+            _ => core,
+        };
+        Some(Rgba([g as u8, g as u8, g as u8, 255]))
+    }
+
+    fn extract(image: &RgbaImage) -> Vec<u8> {
+        let removed: Vec<bool> = image.pixels().map(|p| p[0] > 245 && p[1] > 245).collect();
+        let subject = vec![false; removed.len()];
+        let options = ShadowOptions::default();
+        extract_shadow(
+            image,
+            &removed,
+            &subject,
+            |_| Some([255, 255, 255]),
+            &options,
+        )
+    }
+
+    #[test]
+    fn test_extract_shadow_keeps_cast_shadow_only() {
+        // White backdrop, a red product with a grey label, and a cast shadow.
+        let (w, h) = (40u32, 20u32);
+        let image = RgbaImage::from_fn(w, h, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                if (8..12).contains(&x) && (8..12).contains(&y) {
+                    Rgba([128, 128, 128, 255])
+                } else {
+                    Rgba([200, 30, 30, 255])
+                }
+            } else {
+                cast_shadow(x, y).unwrap_or(Rgba([255, 255, 255, 255]))
+            }
+        });
+        let shadow = extract(&image);
+        let at = |x: u32, y: u32| shadow[(y * w + x) as usize];
+
+        // 140 over a 255 backdrop is 45% darkening.
+        assert!((at(15, 10) as i32 - 115).abs() <= 1);
+        assert!(at(25, 10) > 0 && at(25, 10) < at(15, 10));
+        assert!(at(20, 6) > 0 && at(20, 6) < at(20, 7));
+        assert_eq!(at(10, 10), 0);
+        assert_eq!(at(6, 6), 0);
+        assert_eq!(at(0, 0), 0);
+
+        let layer = shadow_layer(&shadow, w, h);
+        assert_eq!(*layer.get_pixel(15, 10), Rgba([0, 0, 0, at(15, 10)]));
+    }
+
+    #[test]
+    fn test_extract_shadow_keeps_grey_subject() {
+        // Flat grey product touching the white backdrop on every side, casting a shadow.
+        let (w, h) = (40u32, 20u32);
+        let image = RgbaImage::from_fn(w, h, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                Rgba([120, 120, 120, 255])
+            } else {
+                cast_shadow(x, y).unwrap_or(Rgba([255, 255, 255, 255]))
+            }
+        });
+        let shadow = extract(&image);
+        let at = |x: u32, y: u32| shadow[(y * w + x) as usize];
+
+        for (x, y) in [(5, 5), (10, 10), (14, 9), (14, 14)] {
+            assert_eq!(at(x, y), 0);
+        }
+        assert!(at(15, 10) > 0 && at(30, 10) > 0);
+    }
+}
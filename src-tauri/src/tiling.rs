@@ -0,0 +1,636 @@
+use crate::image_processing::{prepare_split_source, write_tile_files, GridGeometry, TileInfo};
+use crate::keying::KeyMatcher;
+use image::imageops::FilterType as ResizeFilterType;
+use image::{DynamicImage, RgbaImage};
+use std::path::Path;
+
+/// Longest side of the thumbnail used to measure local detail.
+const ANALYSIS_MAX_SIDE: u32 = 1024;
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QuadtreeOptions {
+    /// Largest tile side the model can return at full resolution, overlap included.
+    pub max_tile_side: u32,
+    /// Cells are not subdivided below this side length.
+    pub min_tile_side: u32,
+    /// Mean luma gradient (0-255 per pixel) above which a cell counts as detailed.
+    pub detail_threshold: f32,
+    /// Overlap added around each cell, relative to the cell size.
+    pub overlap_ratio: f64,
+}
+
+impl Default for QuadtreeOptions {
+    fn default() -> Self {
+        Self {
+            max_tile_side: 1024,
+            min_tile_side: 256,
+            detail_threshold: 6.0,
+            overlap_ratio: 0.1,
+        }
+    }
+}
+
+/// Summed-area table of the luma gradient of a downscaled copy of the image.
+struct DetailMap {
+    width: u32,
+    height: u32,
+    scale_x: f64,
+    scale_y: f64,
+    integral: Vec<f64>,
+}
+
+impl DetailMap {
+    fn new(image: &RgbaImage) -> Self {
+        let (w, h) = image.dimensions();
+        let longest = w.max(h).max(1);
+        let thumb = if longest > ANALYSIS_MAX_SIDE {
+            let scale = ANALYSIS_MAX_SIDE as f64 / longest as f64;
+            let tw = ((w as f64 * scale).round() as u32).max(1);
+            let th = ((h as f64 * scale).round() as u32).max(1);
+            DynamicImage::ImageRgba8(image.clone())
+                .resize_exact(tw, th, ResizeFilterType::Triangle)
+                .to_luma8()
+        } else {
+            DynamicImage::ImageRgba8(image.clone()).to_luma8()
+        };
+
+        let (tw, th) = thumb.dimensions();
+        let stride = tw as usize + 1;
+        let mut integral = vec![0.0f64; stride * (th as usize + 1)];
+        for y in 0..th {
+            let mut row_sum = 0.0f64;
+            for x in 0..tw {
+                let v = thumb.get_pixel(x, y)[0] as f64;
+                let right = thumb.get_pixel((x + 1).min(tw - 1), y)[0] as f64;
+                let down = thumb.get_pixel(x, (y + 1).min(th - 1))[0] as f64;
+                row_sum += (right - v).abs() + (down - v).abs();
+                let idx = (y as usize + 1) * stride + x as usize + 1;
+                integral[idx] = integral[idx - stride] + row_sum;
+            }
+        }
+
+        Self {
+            width: tw,
+            height: th,
+            scale_x: tw as f64 / w.max(1) as f64,
+            scale_y: th as f64 / h.max(1) as f64,
+            integral,
+        }
+    }
+
+    /// Mean gradient inside a rectangle given in full-resolution coordinates.
+    fn mean(&self, x: u32, y: u32, w: u32, h: u32) -> f32 {
+        let x0 = ((x as f64 * self.scale_x).floor() as u32).min(self.width - 1);
+        let y0 = ((y as f64 * self.scale_y).floor() as u32).min(self.height - 1);
+        let x1 = (((x + w) as f64 * self.scale_x).ceil() as u32).clamp(x0 + 1, self.width);
+        let y1 = (((y + h) as f64 * self.scale_y).ceil() as u32).clamp(y0 + 1, self.height);
+        let stride = self.width as usize + 1;
+        let at = |px: u32, py: u32| self.integral[py as usize * stride + px as usize];
+        let sum = at(x1, y1) - at(x0, y1) - at(x1, y0) + at(x0, y0);
+        (sum / ((x1 - x0) as f64 * (y1 - y0) as f64)) as f32
+    }
+}
+
+/// Plans content-adaptive tiles: detailed regions are subdivided into small tiles,
+/// flat regions stay as large ones. Returns `(x, y, width, height)` rectangles that
+/// already include their overlap and never exceed `max_tile_side`.
+pub fn plan_quadtree(image: &RgbaImage, options: &QuadtreeOptions) -> Vec<(u32, u32, u32, u32)> {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return Vec::new();
+    }
+
+    let overlap_ratio = options.overlap_ratio.clamp(0.0, 0.45);
+    let max_side = options.max_tile_side.max(16);
+    // Leave room for the overlap on both sides so expanded tiles stay within max_side.
+    let core_max = ((max_side as f64 / (1.0 + 2.0 * overlap_ratio)).floor() as u32).max(8);
+    let min_side = options.min_tile_side.clamp(8, core_max);
+    let detail = DetailMap::new(image);
+
+    let root_cols = w.div_ceil(core_max);
+    let root_rows = h.div_ceil(core_max);
+    let mut pending: Vec<(u32, u32, u32, u32)> = Vec::new();
+    for r in 0..root_rows {
+        for c in 0..root_cols {
+            let x0 = (c as u64 * w as u64 / root_cols as u64) as u32;
+            let x1 = ((c + 1) as u64 * w as u64 / root_cols as u64) as u32;
+            let y0 = (r as u64 * h as u64 / root_rows as u64) as u32;
+            let y1 = ((r + 1) as u64 * h as u64 / root_rows as u64) as u32;
+            pending.push((x0, y0, x1 - x0, y1 - y0));
+        }
+    }
+
+    let mut leaves = Vec::new();
+    while let Some((x, y, cw, ch)) = pending.pop() {
+        let can_split = cw / 2 >= min_side && ch / 2 >= min_side;
+        if can_split && detail.mean(x, y, cw, ch) > options.detail_threshold {
+            let (hw, hh) = (cw / 2, ch / 2);
+            pending.push((x, y, hw, hh));
+            pending.push((x + hw, y, cw - hw, hh));
+            pending.push((x, y + hh, hw, ch - hh));
+            pending.push((x + hw, y + hh, cw - hw, ch - hh));
+        } else {
+            leaves.push((x, y, cw, ch));
+        }
+    }
+
+    leaves.sort_unstable_by_key(|&(x, y, _, _)| (y, x));
+    leaves
+        .into_iter()
+        .map(|(x, y, cw, ch)| {
+            let pad_x = (cw as f64 * overlap_ratio).ceil() as u32;
+            let pad_y = (ch as f64 * overlap_ratio).ceil() as u32;
+            let x0 = x.saturating_sub(pad_x);
+            let y0 = y.saturating_sub(pad_y);
+            let x1 = (x + cw + pad_x).min(w);
+            let y1 = (y + ch + pad_y).min(h);
+            (x0, y0, x1 - x0, y1 - y0)
+        })
+        .collect()
+}
+
+/// Splits the input along a quadtree plan. Tiles are numbered in `r`; `c` is always 0.
+pub fn split_image_quadtree(
+    input_path: &str,
+    options: &QuadtreeOptions,
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<(Vec<TileInfo>, u32, u32, String), String> {
+    let (img_rgba, image_format, new_input_path) =
+        prepare_split_source(input_path, prefer_jpeg, output_dir)?;
+    let (w, h) = img_rgba.dimensions();
+
+    let tile_configs = plan_quadtree(&img_rgba, options)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (x, y, tw, th))| (idx as u32, 0, x, y, tw, th))
+        .collect();
+
+    let tiles = write_tile_files(&img_rgba, tile_configs, image_format, output_dir, "")?;
+    Ok((tiles, w, h, new_input_path))
+}
+
+/// Longest side of the thumbnail scanned for the subject bounds.
+const ROI_SCAN_MAX_SIDE: u32 = 512;
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RoiOptions {
+    /// Margin added around the detected subject, relative to its longest side.
+    pub margin_ratio: f64,
+    /// Optional low-resolution model output (subject on the key colour) to scan
+    /// instead of the source image.
+    pub hint_data_url: Option<String>,
+}
+
+impl Default for RoiOptions {
+    fn default() -> Self {
+        Self {
+            margin_ratio: 0.05,
+            hint_data_url: None,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubjectRoi {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Estimates the subject's bounding box in a `width` x `height` frame by scanning a
+/// thumbnail of `scan` for pixels that are not the key colour. Sparse rows and columns
+/// (noise, dust) are ignored. Returns `None` when nothing but key colour is found.
+pub fn estimate_subject_roi(
+    scan: &RgbaImage,
+    width: u32,
+    height: u32,
+    key: &KeyMatcher,
+    margin_ratio: f64,
+) -> Option<SubjectRoi> {
+    let (sw, sh) = scan.dimensions();
+    if sw == 0 || sh == 0 || width == 0 || height == 0 {
+        return None;
+    }
+
+    let longest = sw.max(sh);
+    let thumb = if longest > ROI_SCAN_MAX_SIDE {
+        let scale = ROI_SCAN_MAX_SIDE as f64 / longest as f64;
+        let tw = ((sw as f64 * scale).round() as u32).max(1);
+        let th = ((sh as f64 * scale).round() as u32).max(1);
+        DynamicImage::ImageRgba8(scan.clone())
+            .resize_exact(tw, th, ResizeFilterType::Triangle)
+            .to_rgba8()
+    } else {
+        scan.clone()
+    };
+
+    let (tw, th) = thumb.dimensions();
+    let mut row_counts = vec![0u32; th as usize];
+    let mut col_counts = vec![0u32; tw as usize];
+    for (x, y, px) in thumb.enumerate_pixels() {
+        if !key.is_key(px) {
+            row_counts[y as usize] += 1;
+            col_counts[x as usize] += 1;
+        }
+    }
+
+    let row_min = (tw / 200).max(2);
+    let col_min = (th / 200).max(2);
+    let y0 = row_counts.iter().position(|&n| n >= row_min)? as u32;
+    let y1 = row_counts.iter().rposition(|&n| n >= row_min)? as u32 + 1;
+    let x0 = col_counts.iter().position(|&n| n >= col_min)? as u32;
+    let x1 = col_counts.iter().rposition(|&n| n >= col_min)? as u32 + 1;
+
+    let scale_x = width as f64 / tw as f64;
+    let scale_y = height as f64 / th as f64;
+    let fx0 = x0 as f64 * scale_x;
+    let fy0 = y0 as f64 * scale_y;
+    let fx1 = x1 as f64 * scale_x;
+    let fy1 = y1 as f64 * scale_y;
+    let margin = (fx1 - fx0).max(fy1 - fy0) * margin_ratio.max(0.0);
+
+    let rx0 = (fx0 - margin).floor().max(0.0) as u32;
+    let ry0 = (fy0 - margin).floor().max(0.0) as u32;
+    let rx1 = ((fx1 + margin).ceil() as u32).min(width);
+    let ry1 = ((fy1 + margin).ceil() as u32).min(height);
+    if rx1 <= rx0 || ry1 <= ry0 {
+        return None;
+    }
+
+    Some(SubjectRoi {
+        x: rx0,
+        y: ry0,
+        width: rx1 - rx0,
+        height: ry1 - ry0,
+    })
+}
+
+/// Splits only the subject's region (plus margin) into a `rows x cols` grid.
+///
+/// Tile coordinates are absolute, so the result merges with `merge_tile_rects`,
+/// which fills everything outside the ROI with the key colour or transparency.
+pub fn split_image_roi(
+    input_path: &str,
+    rows: u32,
+    cols: u32,
+    overlap_ratio_x: f64,
+    overlap_ratio_y: f64,
+    key_color: &str,
+    tolerance: u8,
+    options: &RoiOptions,
+    hint: Option<&RgbaImage>,
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<(Vec<TileInfo>, u32, u32, String, SubjectRoi), String> {
+    if rows == 0 || cols == 0 {
+        return Err("Rows and cols must be greater than zero".to_string());
+    }
+
+    let (img_rgba, image_format, new_input_path) =
+        prepare_split_source(input_path, prefer_jpeg, output_dir)?;
+    let (w, h) = img_rgba.dimensions();
+
+    let key = KeyMatcher::parse(key_color, tolerance)?;
+    let roi = estimate_subject_roi(hint.unwrap_or(&img_rgba), w, h, &key, options.margin_ratio)
+    .unwrap_or(SubjectRoi {
+        x: 0,
+        y: 0,
+        width: w,
+        height: h,
+    });
+
+    let grid = GridGeometry::new(
+        roi.width,
+        roi.height,
+        rows,
+        cols,
+        overlap_ratio_x,
+        overlap_ratio_y,
+    )?;
+    let tile_configs = grid
+        .tile_keys()
+        .into_iter()
+        .filter_map(|(r, c)| {
+            let (x, y, tw, th) = grid.tile_rect(r, c)?;
+            Some((r, c, roi.x + x, roi.y + y, tw, th))
+        })
+        .collect();
+
+    let tiles = write_tile_files(&img_rgba, tile_configs, image_format, output_dir, "")?;
+    Ok((tiles, w, h, new_input_path, roi))
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCapabilities {
+    /// Longest side, in pixels, of an image the model returns.
+    pub max_side: u32,
+    /// Output aspect ratios the model supports, as `"w:h"`. Empty means any ratio.
+    #[serde(default)]
+    pub aspect_ratios: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanPreference {
+    /// Fewest calls that keep most of the source resolution.
+    Cost,
+    #[default]
+    Balanced,
+    /// Never ask the model to upscale a tile.
+    Quality,
+}
+
+impl PlanPreference {
+    /// Minimum share of the tile's source resolution the model output must cover.
+    fn min_coverage(self) -> f64 {
+        match self {
+            Self::Cost => 0.6,
+            Self::Balanced => 0.85,
+            Self::Quality => 1.0,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedTile {
+    pub r: u32,
+    pub c: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GridPlan {
+    pub width: u32,
+    pub height: u32,
+    pub rows: u32,
+    pub cols: u32,
+    pub overlap_ratio_x: f64,
+    pub overlap_ratio_y: f64,
+    /// Aspect ratio to request from the model, if it only supports fixed ratios.
+    pub aspect_ratio: Option<String>,
+    /// Model output pixels per source pixel for a full-size tile.
+    pub coverage: f64,
+    pub estimated_calls: u32,
+    pub tiles: Vec<PlannedTile>,
+}
+
+/// Upper bound on rows and cols explored by the planner, the same as the smart grid cap.
+const PLAN_MAX_COUNT: u32 = 64;
+
+fn parse_aspect_ratio(value: &str) -> Option<f64> {
+    let (w, h) = value.trim().split_once(':')?;
+    let w: f64 = w.trim().parse().ok()?;
+    let h: f64 = h.trim().parse().ok()?;
+    (w > 0.0 && h > 0.0).then_some(w / h)
+}
+
+/// Picks the cheapest `rows x cols` grid whose tiles the model can return at the
+/// resolution demanded by `preference`, matching tile shape to a supported ratio.
+pub fn plan_grid(
+    width: u32,
+    height: u32,
+    model: &ModelCapabilities,
+    overlap_ratio: f64,
+    preference: PlanPreference,
+) -> Result<GridPlan, String> {
+    if width == 0 || height == 0 {
+        return Err("Image dimensions must be greater than zero".to_string());
+    }
+    if model.max_side == 0 {
+        return Err("Model max side must be greater than zero".to_string());
+    }
+    let overlap_ratio = overlap_ratio.clamp(0.0, 0.9);
+    let ratios: Vec<(String, f64)> = model
+        .aspect_ratios
+        .iter()
+        .filter_map(|value| Some((value.trim().to_string(), parse_aspect_ratio(value)?)))
+        .collect();
+
+    struct Candidate {
+        rows: u32,
+        cols: u32,
+        aspect_ratio: Option<String>,
+        coverage: f64,
+        mismatch: f64,
+    }
+
+    let mut candidates = Vec::new();
+    for rows in 1..=PLAN_MAX_COUNT {
+        for cols in 1..=PLAN_MAX_COUNT {
+            let grid = GridGeometry::new(width, height, rows, cols, overlap_ratio, overlap_ratio)?;
+            let tile_aspect = grid.tile_w as f64 / grid.tile_h as f64;
+
+            let (aspect_ratio, output_aspect) = ratios
+                .iter()
+                .min_by(|a, b| {
+                    let da = (a.1 / tile_aspect).ln().abs();
+                    let db = (b.1 / tile_aspect).ln().abs();
+                    da.total_cmp(&db)
+                })
+                .map(|(name, ratio)| (Some(name.clone()), *ratio))
+                .unwrap_or((None, tile_aspect));
+
+            let max_side = model.max_side as f64;
+            let (out_w, out_h) = if output_aspect >= 1.0 {
+                (max_side, max_side / output_aspect)
+            } else {
+                (max_side * output_aspect, max_side)
+            };
+            let coverage = (out_w / grid.tile_w as f64).min(out_h / grid.tile_h as f64);
+
+            candidates.push(Candidate {
+                rows,
+                cols,
+                aspect_ratio,
+                coverage,
+                mismatch: (output_aspect / tile_aspect).ln().abs(),
+            });
+        }
+    }
+
+    let min_coverage = preference.min_coverage();
+    let best = candidates
+        .iter()
+        .filter(|c| c.coverage >= min_coverage)
+        .min_by(|a, b| {
+            (a.rows * a.cols)
+                .cmp(&(b.rows * b.cols))
+                .then(a.mismatch.total_cmp(&b.mismatch))
+        })
+        .or_else(|| {
+            candidates
+                .iter()
+                .max_by(|a, b| a.coverage.total_cmp(&b.coverage))
+        })
+        .ok_or("No grid candidates available")?;
+
+    let grid = GridGeometry::new(
+        width,
+        height,
+        best.rows,
+        best.cols,
+        overlap_ratio,
+        overlap_ratio,
+    )?;
+    let tiles: Vec<PlannedTile> = grid
+        .tile_keys()
+        .into_iter()
+        .filter_map(|(r, c)| {
+            let (x, y, tw, th) = grid.tile_rect(r, c)?;
+            Some(PlannedTile {
+                r,
+                c,
+                x,
+                y,
+                width: tw,
+                height: th,
+            })
+        })
+        .collect();
+
+    Ok(GridPlan {
+        width,
+        height,
+        rows: best.rows,
+        cols: best.cols,
+        overlap_ratio_x: overlap_ratio,
+        overlap_ratio_y: overlap_ratio,
+        aspect_ratio: best.aspect_ratio.clone(),
+        coverage: best.coverage,
+        estimated_calls: tiles.len() as u32,
+        tiles,
+    })
+}
+
+/// Fails on the first tile of `plan` that does not fit the planned image.
+fn check_plan_tiles(plan: &GridPlan) -> Result<(), String> {
+    for t in &plan.tiles {
+        let fits = t.width > 0
+            && t.height > 0
+            && t.x + t.width <= plan.width
+            && t.y + t.height <= plan.height;
+        if !fits {
+            return Err(format!(
+                "Grid plan tile {},{} ({}x{} at {},{}) does not fit the {}x{} image",
+                t.r, t.c, t.width, t.height, t.x, t.y, plan.width, plan.height
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Splits the input along a plan returned by `plan_grid`.
+pub fn split_image_with_plan(
+    input_path: &str,
+    plan: &GridPlan,
+    prefer_jpeg: bool,
+    output_dir: &Path,
+) -> Result<(Vec<TileInfo>, u32, u32, String), String> {
+    check_plan_tiles(plan)?;
+    let (img_rgba, image_format, new_input_path) =
+        prepare_split_source(input_path, prefer_jpeg, output_dir)?;
+    let (w, h) = img_rgba.dimensions();
+    if (w, h) != (plan.width, plan.height) {
+        return Err(format!(
+            "Grid plan is for {}x{} but the image is {}x{}",
+            plan.width, plan.height, w, h
+        ));
+    }
+
+    let tile_configs = plan
+        .tiles
+        .iter()
+        .map(|t| (t.r, t.c, t.x, t.y, t.width, t.height))
+        .collect();
+
+    let tiles = write_tile_files(&img_rgba, tile_configs, image_format, output_dir, "")?;
+    Ok((tiles, w, h, new_input_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_quadtree_subdivides_detail_and_respects_max_side() {
+        // Left half is flat, right half is a checkerboard.
+        let image = RgbaImage::from_fn(2048, 1024, |x, y| {
+            if x < 1024 || (x / 8 + y / 8) % 2 == 0 {
+                Rgba([200, 200, 200, 255])
+            } else {
+                Rgba([20, 20, 20, 255])
+            }
+        });
+        let options = QuadtreeOptions::default();
+        let rects = plan_quadtree(&image, &options);
+
+        assert!(rects
+            .iter()
+            .all(|&(_, _, w, h)| w <= options.max_tile_side && h <= options.max_tile_side));
+        let left = rects.iter().filter(|&&(x, _, _, _)| x < 900).count();
+        let right = rects.iter().filter(|&&(x, _, _, _)| x >= 1100).count();
+        assert!(right > left);
+    }
+
+    #[test]
+    fn test_subject_roi_bounds_non_key_pixels_with_margin() {
+        let image = RgbaImage::from_fn(1000, 800, |x, y| {
+            if (400..600).contains(&x) && (300..500).contains(&y) {
+                Rgba([40, 60, 80, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let white = KeyMatcher::parse("white", 10).unwrap();
+        let roi = estimate_subject_roi(&image, 1000, 800, &white, 0.1).unwrap();
+
+        assert!(roi.x <= 380 && roi.x >= 360);
+        assert!(roi.y <= 280 && roi.y >= 260);
+        assert!(roi.x + roi.width >= 620 && roi.x + roi.width <= 640);
+        assert!(roi.y + roi.height >= 520 && roi.y + roi.height <= 540);
+
+        let blank = RgbaImage::from_pixel(64, 64, Rgba([255, 255, 255, 255]));
+        assert!(estimate_subject_roi(&blank, 64, 64, &white, 0.1).is_none());
+    }
+
+    #[test]
+    fn test_plan_grid_prefers_fewest_calls_within_model_resolution() {
+        let model = ModelCapabilities {
+            max_side: 1024,
+            aspect_ratios: vec!["1:1".to_string(), "4:3".to_string(), "3:4".to_string()],
+        };
+        let plan = plan_grid(3000, 2000, &model, 0.1, PlanPreference::Quality).unwrap();
+
+        assert_eq!(plan.estimated_calls, plan.rows * plan.cols);
+        assert!(plan.coverage >= 1.0);
+        let cheaper = plan_grid(3000, 2000, &model, 0.1, PlanPreference::Cost).unwrap();
+        assert!(cheaper.estimated_calls <= plan.estimated_calls);
+        assert!(plan.aspect_ratio.is_some());
+    }
+
+    #[test]
+    fn test_plan_tiles_outside_the_image_are_rejected() {
+        let model = ModelCapabilities {
+            max_side: 1024,
+            aspect_ratios: Vec::new(),
+        };
+        let mut plan = plan_grid(3000, 2000, &model, 0.1, PlanPreference::Quality).unwrap();
+        assert!(check_plan_tiles(&plan).is_ok());
+
+        plan.tiles.last_mut().unwrap().width += 1;
+        let err = check_plan_tiles(&plan).unwrap_err();
+        let last = plan.tiles.last().unwrap();
+        assert!(err.contains(&format!("tile {},{}", last.r, last.c)));
+    }
+}
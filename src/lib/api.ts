@@ -1,6 +1,7 @@
 type GenerateImageOptions = {
   apiBaseUrl?: string;
   fullImageBlob?: Blob | null;
+  maskBlob?: Blob | null;
 };
 
 function normalizeApiBaseUrl(apiBaseUrl?: string): string {
@@ -163,6 +164,11 @@ export async function generateImage(
     parts.push({ text: "Target tile to generate/edit:" });
     parts.push({ inline_data: tileInline });
   }
+  if (options.maskBlob) {
+    const maskInline = await blobToInlineData(options.maskBlob);
+    parts.push({ text: "Mask for the target tile (white = unknown area to fill, black = keep):" });
+    parts.push({ inline_data: maskInline });
+  }
   parts.push({ text: prompt });
 
   const payload = {
@@ -64,6 +64,8 @@ const translations = {
     resizeInput: "Resize input tile to match output",
     concurrency: "Max Concurrency",
     bgRemoval: "Background Removal",
+    dualBackground: "Dual-background alpha",
+    dualBackgroundHint: "Generate each tile on white and on black and solve real transparency from the pair (two requests per tile)",
     bgRemoved: "BGRemoved",
     bgRemovedSuffix: "BGRemoved",
     backgroundColor: "Background Color",
@@ -73,8 +75,23 @@ const translations = {
     colorBlue: "Pure Blue",
     colorCustom: "Custom",
     keyColor: "Key Color",
+    keyMetric: "Match by",
+    keyMetricRgb: "RGB",
+    keyMetricLab: "ΔE2000 (Lab)",
+    keyMetricHue: "Hue / chroma",
+    addKeyColor: "Add key colour",
+    removeKeyColor: "Remove key colour",
     tolerance: "Tolerance",
     mainSubject: "Main Subject",
+    protectMask: "Protect Mask",
+    protectMaskHint: "Black keeps the original, white forces the AI result. Tiles fully covered in black are skipped.",
+    maskPaint: "Paint on canvas",
+    maskBrushProtect: "Protect",
+    maskBrushForce: "Force",
+    maskBrushErase: "Erase",
+    maskBrushSize: "Brush",
+    uploadMask: "Upload Mask",
+    clearMask: "Clear",
     subjectForPrompt: "Subject For Prompt",
     subjectPlaceholder: "Enter subject (e.g. bicycle)",
     usingSubject: "Using",
@@ -100,15 +117,23 @@ const translations = {
     uploadInstruction: "Click or Drop Image Here",
     cropImageAlt: "Image to crop",
     freeAspect: "Free",
+    boxShape: "Selection shape",
+    boxShapeRect: "Box",
+    boxShapeLasso: "Lasso",
     generate: "Generate",
     regenerate: "Regenerate",
     generateInBox: "Generate In Box",
+    fixSeams: "Fix Seams",
+    fixSeamsHint: "Regenerate crops centred on the tile seams and blend them into the result.",
+    fixingSeamsStatus: "Fixing tile seams",
     boxLayers: "Box Layers",
     noBoxLayers: "No box layers yet.",
     drawSelectionHint: "Draw selection boxes on the canvas, then generate.",
     layer: "Layer",
     layerName: "Layer Name",
     layerVersion: "Version",
+    layerOpacity: "Opacity",
+    blendMode: "Blend mode",
     layerQueued: "Queued",
     layerProcessing: "Processing",
     layerFailed: "Failed",
@@ -161,6 +186,11 @@ const translations = {
       verboseLogging: "Verbose Logging (log final prompts)",
       fullImageReference: "Use full image reference",
       alwaysSquareTiles: "Always use square tiles",
+      checkerboard: "Fake transparency checkerboard",
+      checkerboardHint: "Detect grey/white checkerboards painted instead of a background in generated tiles",
+      checkerboardOff: "Ignore",
+      checkerboardRetry: "Flag tile for retry",
+      checkerboardRemove: "Make transparent",
       systemPrompt: "Prompt",
       promptTemplate: "Prompt Template",
       promptTemplateWithReference: "Prompt Template (With Reference)",
@@ -196,6 +226,8 @@ const translations = {
     resizeInput: "调整输入区块以匹配输出",
     concurrency: "最大并发数",
     bgRemoval: "背景移除",
+    dualBackground: "双背景透明度",
+    dualBackgroundHint: "每个分块分别在白底和黑底上生成，并由两者求解真实透明度（每块两次请求）",
     bgRemoved: "已去底",
     bgRemovedSuffix: "已去底",
     backgroundColor: "背景颜色",
@@ -205,8 +237,23 @@ const translations = {
     colorBlue: "纯蓝",
     colorCustom: "自定义",
     keyColor: "抠像颜色",
+    keyMetric: "匹配方式",
+    keyMetricRgb: "RGB",
+    keyMetricLab: "ΔE2000 (Lab)",
+    keyMetricHue: "色相 / 饱和度",
+    addKeyColor: "添加抠像颜色",
+    removeKeyColor: "移除抠像颜色",
     tolerance: "容差",
     mainSubject: "主体识别",
+    protectMask: "保护蒙版",
+    protectMaskHint: "黑色保留原图，白色强制使用 AI 结果。完全被黑色覆盖的分块将被跳过。",
+    maskPaint: "在画布上绘制",
+    maskBrushProtect: "保护",
+    maskBrushForce: "强制",
+    maskBrushErase: "擦除",
+    maskBrushSize: "画笔",
+    uploadMask: "上传蒙版",
+    clearMask: "清除",
     subjectForPrompt: "用于提示词的主体",
     subjectPlaceholder: "输入主体（例如：自行车）",
     usingSubject: "当前使用",
@@ -232,15 +279,23 @@ const translations = {
     uploadInstruction: "点击或拖拽图片到此处",
     cropImageAlt: "待裁剪图片",
     freeAspect: "自由",
+    boxShape: "选区形状",
+    boxShapeRect: "矩形",
+    boxShapeLasso: "套索",
     generate: "生成",
     regenerate: "重新生成",
     generateInBox: "框选生成",
+    fixSeams: "修复接缝",
+    fixSeamsHint: "重新生成以分块接缝为中心的区域，并融合到结果中。",
+    fixingSeamsStatus: "正在修复分块接缝",
     boxLayers: "框选图层",
     noBoxLayers: "暂无框选图层",
     drawSelectionHint: "请先在画布上绘制框选区域，再开始生成。",
     layer: "图层",
     layerName: "图层名称",
     layerVersion: "版本",
+    layerOpacity: "不透明度",
+    blendMode: "混合模式",
     layerQueued: "排队中",
     layerProcessing: "处理中",
     layerFailed: "失败",
@@ -293,6 +348,11 @@ const translations = {
       verboseLogging: "详细日志（记录最终提示词）",
       fullImageReference: "使用整图参考",
       alwaysSquareTiles: "始终使用正方形切片",
+      checkerboard: "伪透明棋盘格",
+      checkerboardHint: "检测生成分块中代替背景绘制的灰白棋盘格",
+      checkerboardOff: "忽略",
+      checkerboardRetry: "标记分块以重试",
+      checkerboardRemove: "转为透明",
       systemPrompt: "提示词",
       promptTemplate: "提示词模板",
       promptTemplateWithReference: "提示词模板（有参考图）",
@@ -328,6 +388,8 @@ const translations = {
     resizeInput: "入力タイルを出力に合わせてリサイズ",
     concurrency: "最大同時実行数",
     bgRemoval: "背景削除",
+    dualBackground: "二重背景アルファ",
+    dualBackgroundHint: "各タイルを白背景と黒背景で生成し、その組から実際の透明度を求めます（1タイルにつき2回リクエスト）",
     bgRemoved: "背景除去済み",
     bgRemovedSuffix: "背景除去済み",
     backgroundColor: "背景色",
@@ -337,8 +399,23 @@ const translations = {
     colorBlue: "純青",
     colorCustom: "カスタム",
     keyColor: "クロマキー色",
+    keyMetric: "判定方式",
+    keyMetricRgb: "RGB",
+    keyMetricLab: "ΔE2000 (Lab)",
+    keyMetricHue: "色相 / 彩度",
+    addKeyColor: "キー色を追加",
+    removeKeyColor: "キー色を削除",
     tolerance: "許容値",
     mainSubject: "主要被写体",
+    protectMask: "保護マスク",
+    protectMaskHint: "黒は元画像を保持し、白は AI 結果を強制します。黒で完全に覆われたタイルはスキップされます。",
+    maskPaint: "キャンバスに描画",
+    maskBrushProtect: "保護",
+    maskBrushForce: "強制",
+    maskBrushErase: "消去",
+    maskBrushSize: "ブラシ",
+    uploadMask: "マスクを読み込む",
+    clearMask: "クリア",
     subjectForPrompt: "プロンプト用の被写体",
     subjectPlaceholder: "被写体を入力（例: 自転車）",
     usingSubject: "使用中",
@@ -364,15 +441,23 @@ const translations = {
     uploadInstruction: "ここをクリックまたは画像をドロップ",
     cropImageAlt: "クロップ対象画像",
     freeAspect: "自由",
+    boxShape: "選択範囲の形状",
+    boxShapeRect: "矩形",
+    boxShapeLasso: "なげなわ",
     generate: "生成",
     regenerate: "再生成",
     generateInBox: "ボックス内を生成",
+    fixSeams: "継ぎ目を修正",
+    fixSeamsHint: "タイルの継ぎ目を中心とした領域を再生成し、結果に合成します。",
+    fixingSeamsStatus: "タイルの継ぎ目を修正中",
     boxLayers: "ボックスレイヤー",
     noBoxLayers: "ボックスレイヤーはまだありません",
     drawSelectionHint: "先にキャンバスで選択ボックスを描いてから生成してください。",
     layer: "レイヤー",
     layerName: "レイヤー名",
     layerVersion: "バージョン",
+    layerOpacity: "不透明度",
+    blendMode: "描画モード",
     layerQueued: "待機中",
     layerProcessing: "処理中",
     layerFailed: "失敗",
@@ -425,6 +510,11 @@ const translations = {
       verboseLogging: "詳細ログ（最終プロンプトを記録）",
       fullImageReference: "全体画像を参照として使用",
       alwaysSquareTiles: "常に正方形タイルを使用",
+      checkerboard: "偽の透明チェッカーボード",
+      checkerboardHint: "生成タイルで背景の代わりに描かれた灰色/白のチェッカーボードを検出します",
+      checkerboardOff: "無視",
+      checkerboardRetry: "タイルを再試行対象にする",
+      checkerboardRemove: "透明にする",
       systemPrompt: "プロンプト",
       promptTemplate: "プロンプトテンプレート",
       promptTemplateWithReference: "プロンプトテンプレート（参照あり）",